@@ -1,15 +1,7 @@
 /*
 TODO LIST:
 - Stack transfers
-- Lexicon stuff (LEX . : UNION IMPORT)
-- Other primitive words: ${ } TO AT DOES ME EXE SELF DEF LITERAL HERE JMP BRA INAT(rename to NEXT) LIT[ ]LIT LITAT $[ ]$ WEAK TIB
-*/
-
-/*
-TODO: Reformulació dels lexicons:
-- No tenim links ni dependències, les paraules han d'existir en el moment d'emprar-les. Eliminem "dep" dels lexicons.
-- Tenim una paraula, UNION, que uneix diversos lexicons en un. Això simplement uneix els "imp" de tots els lexicons en un de sol.
-- Si dos lexicons tenen la mateixa paraula, ens quedem amb l'última que unim.
+- Other primitive words: ${ } AT DEF INAT(rename to NEXT) LITAT WEAK TIB
 */
 
 #![no_std]
@@ -17,8 +9,9 @@ TODO: Reformulació dels lexicons:
 #[macro_use]
 extern crate alloc;
 
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use alloc::vec::Vec;
+use alloc::rc::Rc;
 
 pub const NAME_SIZE: usize = 32;
 pub type WordName = [u8; NAME_SIZE];
@@ -35,6 +28,20 @@ pub fn word_name_from_str(name: &str) -> (WordName, u8) {
     (word_name, name_len)
 }
 
+/// Split a qualified name (`Lexicon:word`) into lexicon name and word name
+pub fn split_qualified(name: &WordName) -> Option<(WordName, WordName)> {
+    let name_len = name.iter().position(|b| *b == 0).unwrap_or(NAME_SIZE);
+    let sep = name[..name_len].iter().position(|b| *b == b':')?;
+    if sep == 0 || sep == name_len - 1 {
+        return None;
+    }
+    let mut lex_name = WordName::default();
+    let mut word_name = WordName::default();
+    lex_name[..sep].copy_from_slice(&name[..sep]);
+    word_name[..name_len - sep - 1].copy_from_slice(&name[sep + 1..name_len]);
+    Some((lex_name, word_name))
+}
+
 #[derive(Debug)]
 /// Error type
 pub enum KrkErr {
@@ -50,7 +57,63 @@ pub enum KrkErr {
     IndexOutOfBounds,
     CouldNotFree,
     WrongSize,
+    UnresolvedLink,
+    DefinitionFull,
+    NotExecuting,
+    Unbalanced,                 // Definition closed with an open control structure
     Other(&'static str, u16),
+    Thrown(KrkInt),             // Code thrown by user code that is not any of the other errors
+}
+
+impl KrkErr {
+    /// Error as a number: built-in errors are negative, user errors (Other) are positive
+    pub fn code(&self) -> KrkInt {
+        match self {
+            KrkErr::StackUnderun => -1,
+            KrkErr::LevelStackUnderun => -2,
+            KrkErr::AuxStackUnderun => -3,
+            KrkErr::WrongType => -4,
+            KrkErr::EmptyTib => -5,
+            KrkErr::NotCompiling => -6,
+            KrkErr::WordNotFound => -7,
+            KrkErr::WrongBuffer => -8,
+            KrkErr::BufferNotFound => -9,
+            KrkErr::IndexOutOfBounds => -10,
+            KrkErr::CouldNotFree => -11,
+            KrkErr::WrongSize => -12,
+            KrkErr::UnresolvedLink => -13,
+            KrkErr::DefinitionFull => -14,
+            KrkErr::NotExecuting => -15,
+            KrkErr::Unbalanced => -16,
+            KrkErr::Other(_, code) => *code as KrkInt,
+            KrkErr::Thrown(code) => *code,
+        }
+    }
+
+    /// Error from a number, the inverse of `code`
+    pub fn from_code(code: KrkInt) -> Option<Self> {
+        match code {
+            -1 => Some(KrkErr::StackUnderun),
+            -2 => Some(KrkErr::LevelStackUnderun),
+            -3 => Some(KrkErr::AuxStackUnderun),
+            -4 => Some(KrkErr::WrongType),
+            -5 => Some(KrkErr::EmptyTib),
+            -6 => Some(KrkErr::NotCompiling),
+            -7 => Some(KrkErr::WordNotFound),
+            -8 => Some(KrkErr::WrongBuffer),
+            -9 => Some(KrkErr::BufferNotFound),
+            -10 => Some(KrkErr::IndexOutOfBounds),
+            -11 => Some(KrkErr::CouldNotFree),
+            -12 => Some(KrkErr::WrongSize),
+            -13 => Some(KrkErr::UnresolvedLink),
+            -14 => Some(KrkErr::DefinitionFull),
+            -15 => Some(KrkErr::NotExecuting),
+            -16 => Some(KrkErr::Unbalanced),
+            1..=0xFFFF => Some(KrkErr::Other("User error", code as u16)),
+            0 => None,
+            _ => Some(KrkErr::Thrown(code)),
+        }
+    }
 }
 
 /// Terminal Input Buffer
@@ -83,6 +146,21 @@ impl<T: Iterator<Item=u8> + Sized> TIB<T> {
         }
         (word_name, i as u8)
     } 
+
+    /// Return raw bytes in the TIB up to a terminator, dropping one whitespace before it
+    pub fn next_until(&mut self, terminator: &[u8]) -> Option<Vec<u8>> {
+        let mut bytes = Vec::new();
+        while !bytes.ends_with(terminator) {
+            bytes.push(self.0.next()?);
+        }
+        bytes.truncate(bytes.len() - terminator.len());
+        if let Some(&b) = bytes.last() {
+            if b <= 32 {
+                bytes.pop();
+            }
+        }
+        Some(bytes)
+    }
 }
 
 pub type KrkInt = i64;
@@ -117,6 +195,10 @@ impl Cell {
         }
     }
 
+    pub fn is_ref(&self) -> bool {
+        matches!(self, Cell::WordRef(_, _) | Cell::AllocRef(_, _))
+    }
+
     pub fn is_number(&self) -> bool {
         match self {
             Cell::Integer(_) | Cell::Float(_) => true,
@@ -135,13 +217,10 @@ impl Cell {
 }
 
 /*
-Reference count, podem passar la responsabilitat del RC a la pila?
-- Push sempre ha de fer in acquire
-- Però pop no, hi ha casos en que fem un pop d'una dada per guardar-la a un altre lloc i no volem tocar RC.
-- Podem crear consume(), que fa un release de la dada.
-
-Altres utilitats de pila:
-- pop/consume_TYPE() per a llegir una dada d'un tipus concret, retorna un Result<TYPE, Err>
+Reference count: every reference stored in the stack, the aux stack, locals, data regions or cell buffers owns one reference.
+- Pushing a copy of a reference acquires it (Interpreter::push_copy).
+- Moving a reference from one place to another doesn't touch the ref_count.
+- Dropping a reference releases it (Interpreter::consume, Interpreter::release_cell).
 */
 
 #[derive(Debug)]
@@ -194,10 +273,68 @@ impl Stack {
         }
     }
 
+    /// Pop the two cells at the top of current stack, as (below, top), or nothing if there aren't two
+    pub fn pop_pair(&mut self) -> Option<(Cell, Cell)> {
+        if self.size() >= 2 {
+            let top = self.stack.pop()?;
+            Some((self.stack.pop()?, top))
+        }
+        else {
+            None
+        }
+    }
+
     /// Size of current stack
     pub fn size(&self) -> usize {
         self.stack.len() - self.base
     }
+
+    /// Number of cells in all stacks
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Current depth and nesting, to restore them later
+    pub fn mark(&self) -> StackMark {
+        StackMark {
+            len: self.stack.len(),
+            base: self.base,
+            nested: self.nested.clone(),
+        }
+    }
+
+    /// Go back to the nested stacks of the mark and return the cells above it.
+    /// If cells below the mark were consumed, the depth is restored with zeros.
+    pub fn restore(&mut self, mark: &StackMark) -> Vec<Cell> {
+        self.nested.clone_from(&mark.nested);
+        self.base = mark.base;
+        if self.stack.len() > mark.len {
+            self.stack.split_off(mark.len)
+        }
+        else {
+            self.stack.resize(mark.len, Cell::Integer(0));
+            Vec::new()
+        }
+    }
+
+    /// Drop all nested stacks and return their cells
+    pub fn unwind(&mut self) -> Vec<Cell> {
+        if self.nested.is_empty() {
+            return Vec::new();
+        }
+        let first_nested_base = self.nested.get(1).copied().unwrap_or(self.base);
+        self.base = self.nested[0];
+        self.nested.clear();
+        self.stack.split_off(first_nested_base)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Stack state saved by `Stack::mark`
+pub struct StackMark {
+    len: usize,
+    base: usize,
+    nested: Vec<usize>,
 }
 
 /// Auxiliary stack
@@ -212,6 +349,19 @@ impl AuxStack {
 
     /// Pop cell
     pub fn pop(&mut self) -> Option<Cell> { self.0.pop() }
+
+    /// Get cell at top without popping it
+    pub fn peek(&self) -> Option<Cell> { self.0.last().copied() }
+
+    /// Number of cells
+    pub fn len(&self) -> usize { self.0.len() }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Remove and return cells above len
+    pub fn truncate(&mut self, len: usize) -> Vec<Cell> {
+        if self.0.len() > len { self.0.split_off(len) } else { Vec::new() }
+    }
 }
 
 #[derive(Debug)]
@@ -234,27 +384,46 @@ impl Allocs {
     }
 
     pub fn alloc(&mut self, size: usize) -> usize {
-        if let Some(alloc_index) = self.free.pop() {
-            self.allocs[alloc_index] = Alloc::new_alloc(size);
-            alloc_index
-        }
-        else {
-            self.allocs.push(Alloc::new_alloc(size));
-            self.allocs.len() - 1
-        }
+        self.insert(Alloc::new_alloc(size))
     }
 
     pub fn balloc(&mut self, size: usize) -> usize {
+        self.insert(Alloc::new_balloc(size))
+    }
+
+    /// Byte buffer with the given contents
+    pub fn balloc_from(&mut self, bytes: Vec<u8>) -> usize {
+        self.insert(Alloc::new_bytes(bytes))
+    }
+
+    fn insert(&mut self, alloc: Alloc) -> usize {
         if let Some(alloc_index) = self.free.pop() {
-            self.allocs[alloc_index] = Alloc::new_balloc(size);
+            self.allocs[alloc_index] = alloc;
             alloc_index
         }
         else {
-            self.allocs.push(Alloc::new_balloc(size));
+            self.allocs.push(alloc);
             self.allocs.len() - 1
         }
     }
 
+    /// Reference count of an alloc, None if it's free
+    pub fn ref_count(&self, alloc_index: usize) -> Option<usize> {
+        match self.allocs.get(alloc_index) {
+            Some(Alloc { buffer: AllocBuffer::Empty, .. }) | None => None,
+            Some(alloc) => Some(alloc.ref_count),
+        }
+    }
+
+    /// Number of allocs not freed
+    pub fn in_use(&self) -> usize {
+        self.allocs.len() - self.free.len()
+    }
+
+    pub fn calloc(&mut self, size: usize) -> usize {
+        self.insert(Alloc::new_calloc(size))
+    }
+
     pub fn acquire(&mut self, alloc_index: usize) -> bool {
         if let Some(alloc) = self.allocs.get(alloc_index) {
             return match alloc.buffer {
@@ -268,27 +437,25 @@ impl Allocs {
         false
     }
 
-    pub fn release(&mut self, alloc_index: usize) -> bool {
-        if let Some(alloc) = self.allocs.get(alloc_index) {
-            return match alloc.buffer {
-                AllocBuffer::Empty => false,
-                AllocBuffer::CellBuffer(_) => todo!("Implement CALLOC"),
-                AllocBuffer::ByteBuffer(_) | AllocBuffer::DataBuffer(_) => {
-                    if self.allocs[alloc_index].ref_count == 1 {
-                        self.allocs[alloc_index] = Alloc::new_empty();
-                        self.free.push(alloc_index);
-                        true
-                    }
-                    else {
-                        false
-                    }
-                },
-            };
+    /// Decrement the reference count and free the alloc when it reaches zero.
+    /// References held by a freed cell buffer are appended to `released`, the caller must release them.
+    pub fn release(&mut self, alloc_index: usize, released: &mut Vec<Cell>) -> bool {
+        if let Some(alloc) = self.allocs.get_mut(alloc_index) {
+            if let AllocBuffer::Empty = alloc.buffer {
+                return false;
+            }
+            alloc.ref_count -= 1;
+            if alloc.ref_count == 0 {
+                let alloc = core::mem::replace(alloc, Alloc::new_empty());
+                if let AllocBuffer::CellBuffer(buf) = alloc.buffer {
+                    released.extend(buf.into_iter().filter(|cell| cell.is_ref()));
+                }
+                self.free.push(alloc_index);
+                return true;
+            }
         }
         false
     }
-
-    //TODO: implement CALLOC stuff
 }
 
 #[derive(Debug)]
@@ -320,7 +487,19 @@ impl Alloc {
         }
     }
 
-    //TODO: implement CALLOC
+    pub fn new_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            ref_count: 1,
+            buffer: AllocBuffer::ByteBuffer(bytes),
+        }
+    }
+
+    pub fn new_calloc(size: usize) -> Self {
+        Self {
+            ref_count: 1,
+            buffer: AllocBuffer::CellBuffer(vec![Cell::Integer(0); size]),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -352,6 +531,15 @@ impl<T: Iterator<Item=u8> + Sized> Word<T> {
         }
     }
 
+    pub fn is_immediate(&self) -> bool {
+        self.immediate
+    }
+
+    /// Immediate words are executed while compiling, instead of being compiled
+    pub fn set_immediate(&mut self, immediate: bool) {
+        self.immediate = immediate;
+    }
+
     pub fn as_defined(&mut self) -> &mut DefinedWord {
         if let WordFlavor::Defined(word) = &mut self.flavor {
             return word;
@@ -397,6 +585,50 @@ impl DefinedWord {
         else { false }
     }
 
+    /// Number of code cells compiled
+    pub fn code_len(&self) -> usize {
+        self.code_len as usize
+    }
+
+    /// Remove all code cells and return them, data cells are kept
+    pub fn clear_code(&mut self) -> Vec<Cell> {
+        let code = self.definition[..self.code_len as usize].to_vec();
+        for cell in &mut self.definition[..self.code_len as usize] {
+            *cell = Cell::Empty;
+        }
+        self.code_len = 0;
+        code
+    }
+
+    /// Replace code references to a word with references to another one, returns the number of cells replaced
+    pub fn replace_code_refs(&mut self, from: usize, to: usize) -> usize {
+        let mut count = 0;
+        for cell in &mut self.definition[..self.code_len as usize] {
+            if let Cell::WordRef(index, offset) = *cell {
+                if index == from {
+                    *cell = Cell::WordRef(to, offset);
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Code and data cells
+    pub fn cells(&self) -> impl Iterator<Item=&Cell> {
+        self.definition[..self.code_len as usize].iter()
+            .chain(self.definition[DEFINITION_SIZE - self.data_len as usize..].iter())
+    }
+
+    /// Replace an already compiled code cell
+    pub fn patch_code(&mut self, index: usize, cell: Cell) -> bool {
+        if index < self.code_len as usize {
+            self.definition[index] = cell;
+            true
+        }
+        else { false }
+    }
+
     pub fn compile_data(&mut self, cell: Cell) -> bool {
         if self.code_len + self.data_len < DEFINITION_SIZE as u8 {
             self.definition[DEFINITION_SIZE - 1 - self.data_len as usize] = cell;
@@ -407,79 +639,246 @@ impl DefinedWord {
     }
 }
 
+/// Primitive function type
+pub type PrimitiveFn<T> = fn(&mut Interpreter<T>) -> Result<(), KrkErr>;
+
+/// Primitive closure type, can capture host state
+pub type PrimitiveClosure<T> = Rc<dyn Fn(&mut Interpreter<T>) -> Result<(), KrkErr>>;
+
 /// Primitive word model
-pub struct PrimitiveWord<T: Iterator<Item=u8> + Sized> {
-    function: fn(&mut Interpreter<T>) -> Result<(), KrkErr>,
+pub enum PrimitiveWord<T: Iterator<Item=u8> + Sized> {
+    Function(PrimitiveFn<T>),
+    Closure(PrimitiveClosure<T>),
 }
 
 impl<T: Iterator<Item=u8> + Sized> PrimitiveWord<T> {
-    pub fn new(function: fn(&mut Interpreter<T>) -> Result<(), KrkErr>) -> Self {
-        Self {
-            function
-        }
+    pub fn new(function: PrimitiveFn<T>) -> Self {
+        Self::Function(function)
+    }
+
+    pub fn from_closure(function: PrimitiveClosure<T>) -> Self {
+        Self::Closure(function)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 /// Link word model
 pub struct LinkWord {
-    index: usize,
+    index: Option<usize>,
 }
 
-#[derive(Debug)]
+impl LinkWord {
+    /// Create an unresolved link
+    pub fn new() -> Self {
+        Self {
+            index: None
+        }
+    }
+
+    /// Index of the linked word, if already resolved
+    pub fn target(&self) -> Option<usize> {
+        self.index
+    }
+}
+
+#[derive(Debug, Clone)]
 /// Lexicon word model
 pub struct LexiconWord {
-    // TODO: rename to "dictionary"
-    imp: HashMap<WordName, usize>,
-    // TODO: remove dep
-    dep: HashMap<WordName, usize>,
+    dictionary: HashMap<WordName, usize>,
+    order: Vec<WordName>,
+    private: HashSet<WordName>,
 }
 
 impl LexiconWord {
     pub fn new() -> Self {
         Self {
-            imp: HashMap::new(),
-            dep: HashMap::new()
+            dictionary: HashMap::new(),
+            order: Vec::new(),
+            private: HashSet::new(),
         }
     }
 
-    pub fn add_word(&mut self, name: WordName, index: usize) {
-        self.imp.insert(name, index);
+    /// Add word to lexicon. New words are always exported, even if they replace a private one.
+    /// Returns the index of the replaced word, if any.
+    pub fn add_word(&mut self, name: WordName, index: usize) -> Option<usize> {
+        let replaced = self.dictionary.insert(name, index);
+        if replaced.is_some() {
+            // Redefined words go to the end of the definition order
+            self.order.retain(|n| *n != name);
+        }
+        self.order.push(name);
+        self.private.remove(&name);
+        replaced
     }
 
-    pub fn add_dependency(&mut self, name: WordName, index: usize) {
-        self.dep.insert(name, index);
+    /// Remove word from lexicon, returns the index of the removed word. The caller must release it, see Words::remove_word.
+    fn remove_word(&mut self, name: &WordName) -> Option<usize> {
+        let index = self.dictionary.remove(name)?;
+        self.order.retain(|n| n != name);
+        self.private.remove(name);
+        Some(index)
     }
 
     pub fn find_word(&self, name: &WordName) -> Option<usize> {
-        if self.imp.contains_key(name) {
-            Some(self.imp[name])
+        self.dictionary.get(name).copied()
+    }
+
+    pub fn contains(&self, name: &WordName) -> bool {
+        self.dictionary.contains_key(name)
+    }
+
+    /// Number of words in the lexicon
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Iterate over word names and word indexes, in definition order
+    pub fn iter(&self) -> impl Iterator<Item=(&WordName, usize)> {
+        self.order.iter().map(move |name| (name, self.dictionary[name]))
+    }
+
+    /// Find a word that is visible from outside the lexicon
+    pub fn find_exported(&self, name: &WordName) -> Option<usize> {
+        if self.private.contains(name) {
+            None
         }
         else {
-            None
+            self.find_word(name)
         }
     }
+
+    /// Make a word private to the lexicon or export it. Returns false if the word is not in the lexicon.
+    pub fn set_private(&mut self, name: &WordName, private: bool) -> bool {
+        if !self.dictionary.contains_key(name) {
+            return false;
+        }
+        if private {
+            self.private.insert(*name);
+        }
+        else {
+            self.private.remove(name);
+        }
+        true
+    }
+
+    pub fn is_private(&self, name: &WordName) -> bool {
+        self.private.contains(name)
+    }
 }
 
 /// Words
 pub struct Words<T: Iterator<Item=u8> + Sized> {
-    words: Vec<Word<T>>,
+    words: Vec<Option<Word<T>>>,
+    free: Vec<usize>,
+    unreferenced: Vec<usize>,
 }
 
 impl<T: Iterator<Item=u8> + Sized> Words<T> {
     pub fn new() -> Self {
         Self {
-            words: Vec::new()
+            words: Vec::new(),
+            free: Vec::new(),
+            unreferenced: Vec::new(),
         }
     }
 
     pub fn add_word(&mut self, word: Word<T>) -> usize {
-        self.words.push(word);
-        self.words.len() - 1
+        if let Some(word_index) = self.free.pop() {
+            self.words[word_index] = Some(word);
+            word_index
+        }
+        else {
+            self.words.push(Some(word));
+            self.words.len() - 1
+        }
     }
 
+    /// Word at index, None if the index is out of bounds or the word was freed
     pub fn word_at(&mut self, index: usize) -> Option<&mut Word<T>> {
-        self.words.get_mut(index)
+        self.words.get_mut(index).and_then(Option::as_mut)
+    }
+
+    /// Number of word slots, including freed ones
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Number of words not freed
+    pub fn in_use(&self) -> usize {
+        self.words.len() - self.free.len()
+    }
+
+    /// Increment the reference count, false if the word doesn't exist
+    pub fn acquire(&mut self, index: usize) -> bool {
+        if let Some(word) = self.word_at(index) {
+            word.ref_count += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Decrement the reference count. Words are not freed right away, they could still be running: unreferenced
+    /// words are freed by Interpreter when it goes back to TIB mode.
+    pub fn release(&mut self, index: usize) -> bool {
+        if let Some(word) = self.word_at(index) {
+            word.ref_count -= 1;
+            if word.ref_count == 0 {
+                self.unreferenced.push(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Free a word if it's still unreferenced. References held by the word are appended to `released`, the caller
+    /// must release them. References of a word to itself are weak and skipped.
+    pub fn free(&mut self, index: usize, released: &mut Vec<Cell>) -> bool {
+        match self.words.get(index) {
+            Some(Some(word)) if word.ref_count == 0 => {},
+            _ => return false,
+        }
+        let word = self.words[index].take().expect("Word already freed");
+        let is_strong = |cell: &Cell| !matches!(cell, Cell::WordRef(word_index, _) if *word_index == index);
+        match word.flavor {
+            WordFlavor::Defined(defined) => {
+                released.extend(defined.cells().filter(|cell| cell.is_ref() && is_strong(cell)));
+            },
+            WordFlavor::Lexicon(lex) => {
+                released.extend(lex.iter().map(|(_, word_index)| Cell::WordRef(word_index, 0)).filter(is_strong));
+            },
+            WordFlavor::Link(LinkWord { index: Some(word_index) }) => {
+                released.push(Cell::WordRef(word_index, 0));
+            },
+            WordFlavor::Link(_) | WordFlavor::Primitive(_) => {},
+        }
+        self.free.push(index);
+        true
+    }
+
+    /// Get lexicon at index, if the word is a lexicon
+    pub fn lexicon(&self, index: usize) -> Option<&LexiconWord> {
+        if let Some(Some(Word { flavor: WordFlavor::Lexicon(lex), .. })) = self.words.get(index) {
+            Some(lex)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Iterate over the indexes of all lexicons
+    pub fn lexicons(&self) -> impl Iterator<Item=usize> + '_ {
+        self.words.iter()
+            .enumerate()
+            .filter(|(_, word)| matches!(word, Some(Word { flavor: WordFlavor::Lexicon(_), .. })))
+            .map(|(index, _)| index)
     }
 
     pub fn lexicon_at(&mut self, index: usize) -> &mut LexiconWord {
@@ -491,7 +890,70 @@ impl<T: Iterator<Item=u8> + Sized> Words<T> {
         panic!("Word at index {} is not a lexicon", index);
     }
 
-    //TODO: implement word acquire and release
+    /// Merge source lexicons into target lexicon, in order. If two lexicons have the same word, the last one wins.
+    pub fn union(&mut self, target: usize, sources: &[usize]) {
+        for source in sources {
+            let source = self.lexicon_at(*source).clone();
+            for (name, index) in source.iter() {
+                if !source.is_private(name) {
+                    self.share_word(target, *name, index);
+                }
+            }
+        }
+    }
+
+    /// Copy an exported word from source lexicon into target lexicon
+    pub fn import(&mut self, target: usize, source: usize, name: &WordName) -> Result<(), KrkErr> {
+        if let Some(word_index) = self.lexicon_at(source).find_exported(name) {
+            self.share_word(target, *name, word_index);
+            Ok(())
+        }
+        else {
+            Err(KrkErr::WordNotFound)
+        }
+    }
+
+    /// Remove a word from a lexicon and release the lexicon's reference to it
+    pub fn remove_word(&mut self, lexicon: usize, name: &WordName) -> Option<usize> {
+        let index = self.lexicon_at(lexicon).remove_word(name)?;
+        self.release_entry(lexicon, index);
+        Some(index)
+    }
+
+    /// Add a word that already belongs to another lexicon, the target lexicon gets its own reference
+    fn share_word(&mut self, lexicon: usize, name: WordName, index: usize) {
+        if index != lexicon {
+            self.acquire(index);
+        }
+        if let Some(replaced) = self.lexicon_at(lexicon).add_word(name, index) {
+            self.release_entry(lexicon, replaced);
+        }
+    }
+
+    /// Release a word that was in a lexicon. The entry of a lexicon for itself is a weak reference, it is not released.
+    fn release_entry(&mut self, lexicon: usize, index: usize) {
+        if index != lexicon {
+            self.release(index);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Handle to a lexicon word
+pub struct LexiconId(usize);
+
+impl LexiconId {
+    /// Index of the lexicon in Words
+    pub fn index(&self) -> usize { self.0 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Handle to a word
+pub struct WordId(usize);
+
+impl WordId {
+    /// Index of the word in Words
+    pub fn index(&self) -> usize { self.0 }
 }
 
 #[derive(Clone, Copy)]
@@ -522,6 +984,27 @@ impl CEP {
             None
         }
     }
+
+    /// There are no more cells to execute
+    pub fn is_finished<T: Iterator<Item=u8> + Sized>(&self, words: &mut Words<T>) -> bool {
+        let defined = words.word_at(self.word_index)
+            .expect("CEP trying to read from an empty word index")
+            .as_defined();
+        defined.code_len <= self.cell_index
+    }
+
+    /// Move pointer after the last cell, to end the execution of the word
+    pub fn finish<T: Iterator<Item=u8> + Sized>(&mut self, words: &mut Words<T>) {
+        let defined = words.word_at(self.word_index)
+            .expect("CEP trying to read from an empty word index")
+            .as_defined();
+        self.cell_index = defined.code_len;
+    }
+
+    /// Move pointer to another cell of the same word
+    pub fn jump(&mut self, cell_index: u8) {
+        self.cell_index = cell_index;
+    }
 }
 
 /// Return Stack
@@ -536,6 +1019,24 @@ impl ReturnStack {
 
     /// Pop pointer
     pub fn pop(&mut self) -> Option<CEP> { self.0.pop() }
+
+    /// Number of pointers
+    pub fn len(&self) -> usize { self.0.len() }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Drop pointers above len
+    pub fn truncate(&mut self, len: usize) { self.0.truncate(len); }
+}
+
+#[derive(Clone)]
+/// State saved by catch, restored when an error is caught
+struct CatchFrame {
+    stack: StackMark,
+    aux_len: usize,
+    ret_len: usize,
+    local_frames_len: usize,
+    cep: Option<CEP>,
 }
 
 pub struct Interpreter<T: Iterator<Item=u8> + Sized> {
@@ -547,9 +1048,17 @@ pub struct Interpreter<T: Iterator<Item=u8> + Sized> {
     ret: ReturnStack,
     current_cep: Option<CEP>,
     lex_in_use: usize,
+    lex_in_def: usize,
     root_lex: usize,
     exec_mode: bool,
-    compiling: Option<Word<T>>,
+    compiling: Vec<(usize, bool, usize)>, // (word index, exec mode to restore when done, stack depth when started)
+    latest: Option<usize>,
+    catch_frames: Vec<CatchFrame>,
+    catch_end: usize,
+    locals: Vec<Cell>,
+    local_frames: Vec<(usize, usize)>, // (return stack depth, base index in locals)
+    local_names: Vec<(usize, WordName)>, // (compiling word index, local name)
+    tib_aux_len: usize, // aux stack size before running the last word from the TIB
 }
 
 impl<T: Iterator<Item=u8> + Sized> Interpreter<T> {
@@ -563,9 +1072,17 @@ impl<T: Iterator<Item=u8> + Sized> Interpreter<T> {
             ret: ReturnStack::new(),
             current_cep: None,
             lex_in_use: 0,
+            lex_in_def: 0,
             root_lex: 0,
             exec_mode: true,
-            compiling: None,
+            compiling: Vec::new(),
+            latest: None,
+            catch_frames: Vec::new(),
+            catch_end: 0,
+            locals: Vec::new(),
+            local_frames: Vec::new(),
+            local_names: Vec::new(),
+            tib_aux_len: 0,
         };
 
         // Create Root lexicon, always at index 0
@@ -573,44 +1090,236 @@ impl<T: Iterator<Item=u8> + Sized> Interpreter<T> {
         _self.words.add_word(Word::new(word_name, name_len, false, WordFlavor::Lexicon(LexiconWord::new())));
         // Root needs a reference to itself to be able to run the "Root" word
         _self.words.lexicon_at(_self.root_lex).add_word(word_name, _self.root_lex);
-        _self.define_core_words(&[
+        // Lexicons in use and in definition hold a reference, Root is both at start
+        _self.words.acquire(_self.root_lex);
+        _self.words.acquire(_self.root_lex);
+        _self.extend_lexicon(_self.root()).primitives(&[
             ("+", false, plus), ("-", false, minus), ("*", false, star), ("/", false, slash), ("%", false, percent),
             ("<", false, smaller), ("=", false, equal), ("and", false, and), ("or", false, or), ("not", false, not),
             ("{", false, open_curly), ("}", true, close_curly), ("(", false, open_parenth), (")", false, close_parenth),
-            ("flush", false, flush), ("size", false, size), ("->aux", false, to_aux), ("aux->", false, from_aux),
-            ("!", false, mem_exlam), ("@", false, mem_at), ("offset", false, mem_offset), ("alloc", false, mem_alloc),
+            ("flush", false, flush), ("size", false, size), ("dup", false, dup), ("drop", false, drop),
+            ("swap", false, swap), ("over", false, over), ("->aux", false, to_aux), ("aux->", false, from_aux),
+            ("!", false, mem_exlam), ("@", false, mem_at), ("offset", false, mem_offset), ("alloc", false, mem_alloc), ("balloc", false, mem_balloc), ("calloc", false, mem_calloc),
+            ("lex", false, lex), (".", false, dot), (":", false, colon), ("union", false, union),
+            ("import", false, import), ("private", false, private),
+            ("jmp", false, jmp), ("bra", false, bra), ("if", true, if_word), ("else", true, else_word), ("then", true, then_word),
+            ("(do)", false, run_do), ("(loop)", false, run_loop), ("do", true, do_word), ("loop", true, loop_word), ("i", false, loop_index),
+            ("leave", false, leave), ("begin", true, begin), ("until", true, until), ("while", true, while_word), ("repeat", true, repeat),
+            ("self", true, self_word), ("lit", false, lit), ("[", true, open_bracket), ("]", true, close_bracket), ("exe", false, exe),
+            ("catch", false, catch), ("throw", false, throw), ("(endcatch)", false, end_catch),
+            ("create", false, create), ("data", false, data), ("does", false, does), ("(does)", false, run_does),
+            ("variable", false, variable), ("constant", false, constant), ("value", false, value), ("(value)", false, mem_at), ("to", true, to),
+            ("me", false, me), ("here", true, here), ("allot", false, allot),
+            ("literal", true, literal), ("(literal)", false, literal), ("lit[", true, open_lit), ("]lit", true, close_lit), ("immediate", false, immediate), ("$[", true, string_literal),
+            ("|", true, bar), ("(locals)", false, run_locals), ("(local@)", false, local_at), ("(local!)", false, local_exlam),
         ]);
+        _self.new_lexicon("String").primitives(&[
+            ("len", false, string_len), ("concat", false, string_concat), ("substr", false, string_substr),
+            ("compare", false, string_compare), ("find", false, string_find), ("upper", false, string_upper),
+            ("lower", false, string_lower), ("split", false, string_split), (">num", false, string_to_num),
+            ("num>", false, num_to_string),
+        ]);
+
+        // Words executed by catch return through this one, that ends the catch
+        let mut catch_end = DefinedWord::new();
+        let end_catch_word = _self.find_in_lexicon(_self.root(), "(endcatch)").expect("(endcatch) not defined");
+        catch_end.compile_code(Cell::WordRef(end_catch_word.index(), 0));
+        _self.catch_end = _self.words.add_word(Word::new(WordName::default(), 0, false, WordFlavor::Defined(catch_end)));
         
         _self
     }
 
-    fn define_core_words(&mut self, list: &[(&str, bool, fn(&mut Interpreter<T>) -> Result<(), KrkErr>)]) {
-        list.iter().for_each(|(word_name, immediate, function)| { self.define_primitive(self.root_lex, word_name, *immediate, *function); });
+    /// Number of calls waiting in the return stack
+    pub fn return_depth(&self) -> usize {
+        self.ret.len()
     }
 
-    pub fn define_primitive(&mut self, lexicon: usize, word_name: &str, immediate: bool, function: fn(&mut Interpreter<T>) -> Result<(), KrkErr>) -> usize {
+    /// Root lexicon
+    pub fn root(&self) -> LexiconId {
+        LexiconId(self.root_lex)
+    }
+
+    /// Create a new lexicon in Root and return a builder to populate it
+    pub fn new_lexicon(&mut self, lex_name: &str) -> LexiconBuilder<'_, T> {
+        let lexicon = self.define_lexicon(self.root(), lex_name);
+        LexiconBuilder::new(self, lexicon)
+    }
+
+    /// Return a builder to add words to an existing lexicon
+    pub fn extend_lexicon(&mut self, lexicon: LexiconId) -> LexiconBuilder<'_, T> {
+        LexiconBuilder::new(self, lexicon)
+    }
+
+    pub fn define_primitive(&mut self, lexicon: LexiconId, word_name: &str, immediate: bool, function: PrimitiveFn<T>) -> WordId {
+        self.add_primitive(lexicon, word_name, immediate, PrimitiveWord::new(function))
+    }
+
+    /// Define a primitive backed by a closure, that can capture configuration or host state
+    pub fn define_closure<F>(&mut self, lexicon: LexiconId, word_name: &str, immediate: bool, function: F) -> WordId
+        where F: Fn(&mut Interpreter<T>) -> Result<(), KrkErr> + 'static
+    {
+        self.add_primitive(lexicon, word_name, immediate, PrimitiveWord::from_closure(Rc::new(function)))
+    }
+
+    fn add_primitive(&mut self, lexicon: LexiconId, word_name: &str, immediate: bool, primitive: PrimitiveWord<T>) -> WordId {
         let (word_name, name_len) = word_name_from_str(word_name);
-        let word_index = self.words.add_word(Word::new(word_name, name_len, immediate, WordFlavor::Primitive(PrimitiveWord::new(function))));
-        let lex = self.words.lexicon_at(lexicon);
-        lex.add_word(word_name, word_index);
+        let word_index = self.words.add_word(Word::new(word_name, name_len, immediate, WordFlavor::Primitive(primitive)));
+        self.add_to_lexicon(lexicon.0, word_name, word_index);
+        WordId(word_index)
+    }
+
+    pub fn define_lexicon(&mut self, lexicon: LexiconId, lex_name: &str) -> LexiconId {
+        let (word_name, name_len) = word_name_from_str(lex_name);
+        LexiconId(self.add_lexicon(lexicon.0, word_name, name_len))
+    }
+
+    /// Remove a word from a lexicon, it's freed if nothing else references it
+    pub fn remove_word(&mut self, lexicon: LexiconId, word_name: &str) -> bool {
+        let (word_name, _) = word_name_from_str(word_name);
+        self.words.remove_word(lexicon.0, &word_name).is_some()
+    }
+
+    /// Find a word in a lexicon by name
+    pub fn find_in_lexicon(&mut self, lexicon: LexiconId, word_name: &str) -> Option<WordId> {
+        let (word_name, _) = word_name_from_str(word_name);
+        self.words.lexicon_at(lexicon.0).find_word(&word_name).map(WordId)
+    }
+
+    fn add_lexicon(&mut self, lexicon: usize, word_name: WordName, name_len: u8) -> usize {
+        let word_index = self.words.add_word(Word::new(word_name, name_len, false, WordFlavor::Lexicon(LexiconWord::new())));
+        // Like Root, a lexicon needs a reference to itself to be reachable while in use
+        self.words.lexicon_at(word_index).add_word(word_name, word_index);
+        self.add_to_lexicon(lexicon, word_name, word_index);
         word_index
     }
 
-    pub fn run_step(&mut self) -> Result<bool, KrkErr> {
-        if let Ok(true) = self.exec_def_word_step() {
-            // Executing a defined word
-            return Ok(true);
-        }
-        // Running words from the TIB
-        let (word_name, name_len) = self.tib.next_word();
-        if name_len == 0 {
-            return Ok(false);
-        }
-        if self.exec_mode {
-            self.run_in_exec_mode(word_name, name_len)
+    /// Add word to lexicon, resolving the link with the same name, if any
+    fn add_to_lexicon(&mut self, lexicon: usize, word_name: WordName, word_index: usize) {
+        if let Some(link_index) = self.words.lexicon_at(lexicon).find_word(&word_name) {
+            if let Some(Word { flavor: WordFlavor::Link(link), .. }) = self.words.word_at(link_index) {
+                if link.index.is_none() {
+                    link.index = Some(word_index);
+                    self.words.acquire(word_index);
+                }
+            }
         }
-        else {
-            self.run_in_compile_mode(word_name, name_len)
+        if let Some(replaced) = self.words.lexicon_at(lexicon).add_word(word_name, word_index) {
+            self.words.release_entry(lexicon, replaced);
+        }
+        self.latest = Some(word_index);
+    }
+
+    /// Find a word by name, returns word index and the index of the lexicon it was found in.
+    /// Lookup order is: lexicon in use first, then Root, and finally qualified names (`Lexicon:word`).
+    pub fn lookup(&mut self, word_name: &WordName) -> Option<(usize, usize)> {
+        if let Some(found) = self.lookup_unqualified(word_name) {
+            Some(found)
+        }
+        else if let Some((lex_name, word_name)) = split_qualified(word_name) {
+            let (lex_index, _) = self.lookup_unqualified(&lex_name)?;
+            if let Some(Word { flavor: WordFlavor::Lexicon(lex), .. }) = self.words.word_at(lex_index) {
+                lex.find_exported(&word_name).map(|word_index| (word_index, lex_index))
+            }
+            else {
+                None
+            }
+        }
+        else {
+            None
+        }
+    }
+
+    fn lookup_unqualified(&mut self, word_name: &WordName) -> Option<(usize, usize)> {
+        if let Some(word_index) = self.words.lexicon_at(self.lex_in_use).find_word(word_name) {
+            Some((word_index, self.lex_in_use))
+        }
+        else if self.lex_in_use != self.root_lex {
+            self.words.lexicon_at(self.root_lex).find_exported(word_name).map(|word_index| (word_index, self.root_lex))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Index of the lexicon referenced by a cell
+    fn lexicon_index(&self, cell: Cell) -> Option<usize> {
+        if let Cell::WordRef(word_index, _) = cell {
+            self.words.lexicon(word_index).map(|_| word_index)
+        }
+        else {
+            None
+        }
+    }
+
+    fn pop_lexicon(&mut self) -> Result<usize, KrkErr> {
+        if let Some(cell) = self.stack.pop() {
+            // The lexicon is still referenced by its parent, releasing won't free it
+            self.release_cell(cell);
+            self.lexicon_index(cell).ok_or(KrkErr::WrongType)
+        }
+        else {
+            Err(KrkErr::StackUnderun)
+        }
+    }
+
+    pub fn run_step(&mut self) -> Result<bool, KrkErr> {
+        match self.step() {
+            Err(err) => self.recover(err),
+            result => result,
+        }
+    }
+
+    /// Restore the state saved by the innermost catch and push the error code, or abort execution if not catching
+    fn recover(&mut self, err: KrkErr) -> Result<bool, KrkErr> {
+        if let Some(frame) = self.catch_frames.pop() {
+            for cell in self.stack.restore(&frame.stack).into_iter().chain(self.aux.truncate(frame.aux_len)) {
+                self.release_cell(cell);
+            }
+            self.ret.truncate(frame.ret_len);
+            while self.local_frames.len() > frame.local_frames_len {
+                self.release_local_frame();
+            }
+            self.current_cep = frame.cep;
+            self.stack.push(Cell::Integer(err.code()));
+            Ok(true)
+        }
+        else {
+            // Uncaught error, back to TIB mode and to the outermost stack. Loop state left in the aux stack is dropped.
+            for cell in self.stack.unwind().into_iter().chain(self.aux.truncate(self.tib_aux_len)) {
+                self.release_cell(cell);
+            }
+            self.current_cep = None;
+            self.ret.truncate(0);
+            while !self.local_frames.is_empty() {
+                self.release_local_frame();
+            }
+            // Words being compiled are dropped
+            while let Some((word_index, _, _)) = self.compiling.pop() {
+                self.words.release(word_index);
+            }
+            self.local_names.clear();
+            self.exec_mode = true;
+            Err(err)
+        }
+    }
+
+    fn step(&mut self) -> Result<bool, KrkErr> {
+        if self.exec_def_word_step()? {
+            // Executing a defined word
+            return Ok(true);
+        }
+        // Running words from the TIB, nothing is executing so words can be freed
+        self.free_words();
+        self.tib_aux_len = self.aux.len();
+        let (word_name, name_len) = self.tib.next_word();
+        if name_len == 0 {
+            return Ok(false);
+        }
+        if self.exec_mode {
+            self.run_in_exec_mode(word_name, name_len)
+        }
+        else {
+            self.run_in_compile_mode(word_name, name_len)
         }
     }
 
@@ -619,18 +1328,11 @@ impl<T: Iterator<Item=u8> + Sized> Interpreter<T> {
             self.stack.push(num_cell);
         }
         else {
-            let lex = self.words.lexicon_at(self.lex_in_use);
-            if let Some(word_index) = lex.find_word(&word_name) {
+            if let Some((word_index, _)) = self.lookup(&word_name) {
                 self.exec_word(word_index)?;
             }
             else {
-                if self.lex_in_use != self.root_lex {
-                    //TODO: try to find word in Root
-                    todo!("try to find word in Root to run")
-                }
-                else {
-                    return Err(KrkErr::WordNotFound);
-                }
+                return Err(KrkErr::WordNotFound);
             }
         }
         Ok(true)
@@ -638,51 +1340,409 @@ impl<T: Iterator<Item=u8> + Sized> Interpreter<T> {
 
     fn run_in_compile_mode(&mut self, word_name: WordName, name_len: u8)  -> Result<bool, KrkErr> {
         if let Some(num_cell) = Cell::number(word_name, name_len) {
-            let compiling_word = self.compiling
-                .as_mut()
-                .expect("No compiling word while in compilation mode")
-                .as_defined();
-            compiling_word.compile_code(num_cell);
+            self.compile(num_cell)?;
+        }
+        else if let Some(local) = self.find_local(&word_name) {
+            self.compile_root_word("(local@)")?;
+            self.compile(Cell::Integer(local as KrkInt))?;
+        }
+        else if self.is_outer_local(&word_name) {
+            // Don't compile a link that will never be resolved
+            return Err(KrkErr::WordNotFound);
         }
         else {
-            let lex = self.words.lexicon_at(self.lex_in_use);
-            if let Some(word_index) = lex.find_word(&word_name) {
+            // Words of the definitions lexicon are also visible while compiling
+            let found = self.lookup(&word_name)
+                .map(|(word_index, _)| word_index)
+                .or_else(|| self.words.lexicon_at(self.lex_in_def).find_word(&word_name));
+            if let Some(word_index) = found {
                 let word = self.words.word_at(word_index).unwrap_or_else(|| panic!("Word not found at index {}", word_index));
                 if word.immediate {
                     self.exec_word(word_index)?;
                 }
                 else {
-                    let compiling_word = self.compiling
-                        .as_mut()
-                        .expect("No compiling word while in compilation mode")
-                        .as_defined();
-                    word.ref_count += 1; // manual acquire
-                    compiling_word.compile_code(Cell::WordRef(word_index, 0));
+                    self.compile(Cell::WordRef(word_index, 0))?;
+                    self.words.acquire(word_index);
+                }
+            }
+            else if split_qualified(&word_name).is_some() {
+                return Err(KrkErr::WordNotFound);
+            }
+            else {
+                // Word doesn't exist yet, compile a link that will be resolved when the word is defined
+                let link_index = self.words.add_word(Word::new(word_name, name_len, false, WordFlavor::Link(LinkWord::new())));
+                self.words.lexicon_at(self.lex_in_def).add_word(word_name, link_index);
+                self.compile(Cell::WordRef(link_index, 0))?;
+                self.words.acquire(link_index);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Word currently being compiled
+    fn compiling_word(&mut self) -> Result<&mut DefinedWord, KrkErr> {
+        if let Some(&(word_index, _, _)) = self.compiling.last() {
+            Ok(self.words.word_at(word_index).expect("Compiling word doesn't exist").as_defined())
+        }
+        else {
+            Err(KrkErr::NotCompiling)
+        }
+    }
+
+    /// Compile a cell into the word currently being compiled
+    fn compile(&mut self, cell: Cell) -> Result<(), KrkErr> {
+        if self.compiling_word()?.compile_code(cell) {
+            Ok(())
+        }
+        else {
+            Err(KrkErr::DefinitionFull)
+        }
+    }
+
+    /// Compile a cell that pushes itself when executed, the cell is released if it can't be compiled
+    fn compile_literal(&mut self, cell: Cell) -> Result<(), KrkErr> {
+        let result = if let Cell::WordRef(_, _) = cell {
+            // Word references are executed, unless preceded by "lit"
+            self.compile_root_word("lit").and_then(|_| self.compile(cell))
+        }
+        else {
+            self.compile(cell)
+        };
+        if result.is_err() {
+            self.release_cell(cell);
+        }
+        result
+    }
+
+    /// Compile a reference to a Root word
+    fn compile_root_word(&mut self, name: &str) -> Result<(), KrkErr> {
+        let word_index = self.root_word(name)?;
+        self.compile(Cell::WordRef(word_index, 0))?;
+        self.words.acquire(word_index);
+        Ok(())
+    }
+
+    /// Find a word in Root by name
+    fn root_word(&mut self, name: &str) -> Result<usize, KrkErr> {
+        let (word_name, _) = word_name_from_str(name);
+        self.words.lexicon_at(self.root_lex).find_word(&word_name).ok_or(KrkErr::WordNotFound)
+    }
+
+    /// Create a word that pushes a reference to its own data region, name is taken from the TIB
+    fn create_word(&mut self) -> Result<usize, KrkErr> {
+        let (word_name, name_len) = self.tib.next_word();
+        if name_len == 0 {
+            return Err(KrkErr::EmptyTib);
+        }
+        let lit_index = self.root_word("lit")?;
+        let word_index = self.words.add_word(Word::new(word_name, name_len, false, WordFlavor::Defined(DefinedWord::new())));
+        let defined = self.words.word_at(word_index).expect("Created word doesn't exist").as_defined();
+        defined.compile_code(Cell::WordRef(lit_index, 0));
+        defined.compile_code(Cell::WordRef(word_index, 0)); // weak self reference
+        self.words.acquire(lit_index);
+        self.add_to_lexicon(self.lex_in_def, word_name, word_index);
+        Ok(word_index)
+    }
+
+    /// Create a word that reads its own data with the given root word, the data is taken from the stack
+    fn create_reader(&mut self, reader: &str) -> Result<(), KrkErr> {
+        if let Some(cell) = self.stack.pop() {
+            let reader_index = self.root_word(reader)?;
+            let word_index = match self.create_word() {
+                Ok(word_index) => word_index,
+                Err(err) => {
+                    self.release_cell(cell);
+                    return Err(err);
                 }
+            };
+            let defined = self.words.word_at(word_index).expect("Created word doesn't exist").as_defined();
+            defined.compile_data(cell);
+            defined.compile_code(Cell::WordRef(reader_index, 0));
+            self.words.acquire(reader_index);
+            Ok(())
+        }
+        else {
+            Err(KrkErr::StackUnderun)
+        }
+    }
+
+    /// Word was created with `value`
+    fn is_value(&mut self, word_index: usize) -> bool {
+        let value_index = self.root_word("(value)").ok();
+        if let Some(Word { flavor: WordFlavor::Defined(defined), .. }) = self.words.word_at(word_index) {
+            defined.code_len() == 3 && matches!(defined.definition[2], Cell::WordRef(index, _) if Some(index) == value_index)
+        }
+        else {
+            false
+        }
+    }
+
+    /// Word that receives data cells: the one being compiled, or the latest defined
+    fn data_target(&mut self) -> Result<&mut DefinedWord, KrkErr> {
+        let word_index = self.compiling.last().map(|(word_index, _, _)| *word_index).or(self.latest).ok_or(KrkErr::WordNotFound)?;
+        match self.words.word_at(word_index) {
+            Some(Word { flavor: WordFlavor::Defined(defined), .. }) => Ok(defined),
+            _ => Err(KrkErr::WrongType),
+        }
+    }
+
+    /// Read next cell of the running defined word, used by primitives that take inline arguments
+    fn next_inline_cell(&mut self) -> Result<Cell, KrkErr> {
+        if let Some(cep) = &mut self.current_cep {
+            cep.next_cell(&mut self.words).ok_or(KrkErr::IndexOutOfBounds)
+        }
+        else {
+            Err(KrkErr::NotExecuting)
+        }
+    }
+
+    /// Set the cell to execute next in the running defined word
+    fn jump_to(&mut self, target: Cell) -> Result<(), KrkErr> {
+        if let Cell::Integer(cell_index) = target {
+            self.current_cep.as_mut().ok_or(KrkErr::NotExecuting)?.jump(cell_index as u8);
+            Ok(())
+        }
+        else {
+            Err(KrkErr::WrongType)
+        }
+    }
+
+    /// Compile a branch word with a placeholder target, returns the position of the target cell
+    fn compile_forward_branch(&mut self, branch_word: &str) -> Result<usize, KrkErr> {
+        self.compile_root_word(branch_word)?;
+        self.compile(Cell::Integer(0))?;
+        Ok(self.compiling_word()?.code_len() - 1)
+    }
+
+    /// Compile a branch word with a known target
+    fn compile_backward_branch(&mut self, branch_word: &str, target: Cell) -> Result<(), KrkErr> {
+        self.compile_root_word(branch_word)?;
+        self.compile(target)
+    }
+
+    /// Pop loop state (index, limit and exit) from the aux stack
+    fn pop_loop(&mut self) -> Result<(KrkInt, KrkInt, Cell), KrkErr> {
+        if self.aux.len() < 3 {
+            return Err(KrkErr::AuxStackUnderun);
+        }
+        let cells = self.aux.truncate(self.aux.len() - 3);
+        if let [exit, Cell::Integer(limit), Cell::Integer(index)] = cells[..] {
+            Ok((index, limit, exit))
+        }
+        else {
+            for cell in cells {
+                self.release_cell(cell);
+            }
+            Err(KrkErr::WrongType)
+        }
+    }
+
+    /// Pop a position left by a control structure of the word being compiled, cells below it belong to the user
+    fn pop_control(&mut self) -> Result<KrkInt, KrkErr> {
+        let &(_, _, depth) = self.compiling.last().ok_or(KrkErr::NotCompiling)?;
+        if self.stack.depth() <= depth {
+            return Err(KrkErr::StackUnderun);
+        }
+        match self.stack.pop() {
+            Some(Cell::Integer(position)) => Ok(position),
+            Some(cell) => {
+                self.release_cell(cell);
+                Err(KrkErr::WrongType)
+            },
+            None => Err(KrkErr::StackUnderun),
+        }
+    }
+
+    /// Set the target of a forward branch to the current compile position
+    fn resolve_forward_branch(&mut self) -> Result<(), KrkErr> {
+        let position = self.pop_control()?;
+        let compiling_word = self.compiling_word()?;
+        let target = Cell::Integer(compiling_word.code_len() as KrkInt);
+        if compiling_word.patch_code(position as usize, target) {
+            Ok(())
+        }
+        else {
+            Err(KrkErr::IndexOutOfBounds)
+        }
+    }
+
+    /// Start executing a defined word at the cell pointed by the CEP
+    fn call(&mut self, cep: CEP) {
+        // If we are executing a defined word, save current CEP to return later.
+        // Tail calls don't need to return, so the current CEP is just replaced.
+        if let Some(current_cep) = self.current_cep {
+            if current_cep.is_finished(&mut self.words) {
+                self.end_word();
             }
             else {
-                if self.lex_in_use != self.root_lex {
-                    //TODO: try to find word in Root
-                    todo!("try to find word in Root to compile")
+                self.ret.push(current_cep);
+            }
+        }
+        self.current_cep = Some(cep);
+    }
+
+    /// Clean up after the current word finished
+    fn end_word(&mut self) {
+        // Release locals of the current word, if it has any
+        if let Some(&(ret_len, _)) = self.local_frames.last() {
+            if ret_len == self.ret.len() {
+                self.release_local_frame();
+            }
+        }
+    }
+
+    /// Free unreferenced words, and the words and allocs only they referenced. Only safe in TIB mode.
+    fn free_words(&mut self) {
+        while let Some(word_index) = self.words.unreferenced.pop() {
+            let mut released = Vec::new();
+            if self.words.free(word_index, &mut released) {
+                if self.latest == Some(word_index) {
+                    self.latest = None;
+                }
+                for cell in released {
+                    self.release_cell(cell);
+                }
+            }
+        }
+    }
+
+    fn release_local_frame(&mut self) {
+        if let Some((_, base)) = self.local_frames.pop() {
+            while self.locals.len() > base {
+                let cell = self.locals.pop().expect("Locals underflow");
+                self.release_cell(cell);
+            }
+        }
+    }
+
+    /// Push a copy of a cell, acquiring it if it's a reference
+    pub fn push_copy(&mut self, cell: Cell) {
+        self.acquire_cell(cell);
+        self.stack.push(cell);
+    }
+
+    /// Pop a cell and drop it, releasing it if it's a reference
+    pub fn consume(&mut self) -> Result<(), KrkErr> {
+        let cell = self.stack.pop().ok_or(KrkErr::StackUnderun)?;
+        self.release_cell(cell);
+        Ok(())
+    }
+
+    /// Increment the reference count of the referenced word or alloc
+    pub fn acquire_cell(&mut self, cell: Cell) {
+        match cell {
+            Cell::WordRef(index, _) => { self.words.acquire(index); },
+            Cell::AllocRef(index, _) => { self.allocs.acquire(index); },
+            _ => {},
+        }
+    }
+
+    /// Decrement the reference count of the referenced word or alloc, releasing the contents of freed cell buffers.
+    /// Unreferenced words are freed later, see free_words.
+    pub fn release_cell(&mut self, cell: Cell) {
+        let mut pending = vec![cell];
+        while let Some(cell) = pending.pop() {
+            match cell {
+                Cell::WordRef(index, _) => { self.words.release(index); },
+                Cell::AllocRef(index, _) => { self.allocs.release(index, &mut pending); },
+                _ => {},
+            }
+        }
+    }
+
+    /// Pop a string reference and return a copy of its bytes, from the offset to the end
+    fn pop_string(&mut self) -> Result<Vec<u8>, KrkErr> {
+        if let Some(cell) = self.stack.pop() {
+            let bytes = if let Cell::AllocRef(alloc_ref, offset) = cell {
+                if let Some(alloc) = self.allocs.alloc_at(alloc_ref) {
+                    if let AllocBuffer::ByteBuffer(buf) = &alloc.buffer {
+                        Ok(buf.get(offset..).unwrap_or_default().to_vec())
+                    }
+                    else {
+                        Err(KrkErr::WrongBuffer)
+                    }
                 }
                 else {
-                    //TODO: compile a dependency (link)
-                    todo!("compile a dependency")
+                    Err(KrkErr::BufferNotFound)
                 }
             }
+            else {
+                Err(KrkErr::WrongType)
+            };
+            // The copy is all we need, the reference taken from the stack is dropped
+            self.release_cell(cell);
+            bytes
         }
-        Ok(true)
+        else {
+            Err(KrkErr::StackUnderun)
+        }
+    }
+
+    /// Push a new string with the given contents
+    fn push_string(&mut self, bytes: Vec<u8>) {
+        let alloc_ref = self.allocs.balloc_from(bytes);
+        self.stack.push(Cell::AllocRef(alloc_ref, 0));
+    }
+
+    fn pop_integer(&mut self) -> Result<KrkInt, KrkErr> {
+        match self.stack.pop() {
+            Some(Cell::Integer(n)) => Ok(n),
+            Some(cell) => {
+                self.release_cell(cell);
+                Err(KrkErr::WrongType)
+            },
+            None => Err(KrkErr::StackUnderun),
+        }
+    }
+
+    /// Position of a local of the word being compiled
+    fn find_local(&mut self, word_name: &WordName) -> Option<usize> {
+        // Quotations don't have access to the locals of the word that contains them
+        let (word_index, _, _) = *self.compiling.last()?;
+        self.local_names.iter()
+            .filter(|(owner, _)| *owner == word_index)
+            .position(|(_, name)| name == word_name)
+    }
+
+    /// Local of a word that contains the quotation being compiled
+    fn is_outer_local(&self, word_name: &WordName) -> bool {
+        self.local_names.iter().any(|(_, name)| name == word_name)
+    }
+
+    /// Current frame of locals, with the position of a local
+    fn local_index(&mut self) -> Result<usize, KrkErr> {
+        if let (Cell::Integer(index), Some(&(ret_len, base))) = (self.next_inline_cell()?, self.local_frames.last()) {
+            if ret_len != self.ret.len() {
+                // The frame belongs to a caller, the running word has no locals
+                return Err(KrkErr::IndexOutOfBounds);
+            }
+            let index = base + index as usize;
+            if index < self.locals.len() {
+                return Ok(index);
+            }
+            return Err(KrkErr::IndexOutOfBounds);
+        }
+        Err(KrkErr::WrongType)
     }
 
     fn exec_word(&mut self, word_index: usize) -> Result<(), KrkErr> {
         let word = self.words.word_at(word_index).unwrap_or_else(|| panic!("Word not found at index {}", word_index));
         match &word.flavor {
-            WordFlavor::Defined(_) => self.current_cep = Some(CEP::new(word_index)),
-            WordFlavor::Primitive(primitive) => (primitive.function)(self)?,
-            WordFlavor::Lexicon(_) => self.stack.push(Cell::WordRef(word_index, 0)),
-            WordFlavor::Link(_) => {
-                // TODO: point to another word and try to execute
-                todo!("point to another word and try to execute")
+            WordFlavor::Defined(_) => self.call(CEP::new(word_index)),
+            WordFlavor::Primitive(PrimitiveWord::Function(function)) => function(self)?,
+            WordFlavor::Primitive(PrimitiveWord::Closure(closure)) => {
+                // Keep the closure alive while it runs, it could redefine its own word
+                let closure = closure.clone();
+                closure(self)?;
+            },
+            WordFlavor::Lexicon(_) => self.push_copy(Cell::WordRef(word_index, 0)),
+            WordFlavor::Link(link) => {
+                if let Some(link_index) = link.index {
+                    self.exec_word(link_index)?;
+                }
+                else {
+                    return Err(KrkErr::UnresolvedLink);
+                }
             },
         }
         Ok(())
@@ -695,30 +1755,16 @@ impl<T: Iterator<Item=u8> + Sized> Interpreter<T> {
                 // Cell available
                 match next_cell {
                     Cell::Empty => panic!("Executing an empty cell"),
-                    Cell::Integer(_) | Cell::Float(_) | Cell::AllocRef(_,_) => self.stack.push(next_cell),
-                    Cell::WordRef(w_index,_) => {
-                        if let Some(word) = self.words.word_at(w_index) {
-                            match &word.flavor {
-                                WordFlavor::Defined(_) => {
-                                    self.ret.push(*cep);
-                                    self.current_cep = Some(CEP::new(w_index));
-                                },
-                                WordFlavor::Primitive(p) => {
-                                    (p.function)(self)?;
-                                },
-                                WordFlavor::Lexicon(_) => self.stack.push(next_cell),
-                                WordFlavor::Link(_) => todo!("Exec a link in a defined word"),
-                            }
-                        }
-                        else {
-                            panic!("Executing a word that doesn't exist")
-                        }
-                    },
+                    Cell::Integer(_) | Cell::Float(_) => self.stack.push(next_cell),
+                    // The definition keeps its own reference
+                    Cell::AllocRef(_,_) => self.push_copy(next_cell),
+                    Cell::WordRef(w_index,_) => self.exec_word(w_index)?,
                 }
                 Ok(true)
             }
             else {
                 // No more cells to execute: pop CEP from return stack and use as current CEP
+                self.end_word();
                 if let Some(cep) = self.ret.pop() {
                     self.current_cep = Some(cep);
                     Ok(true)
@@ -737,10 +1783,56 @@ impl<T: Iterator<Item=u8> + Sized> Interpreter<T> {
     }
 }
 
-//TODO: primitives are responsible for updating the ref_count of objectes they handle (if they work with references).
+/// Builder to register a set of primitives in a lexicon
+pub struct LexiconBuilder<'a, T: Iterator<Item=u8> + Sized> {
+    interpreter: &'a mut Interpreter<T>,
+    lexicon: LexiconId,
+}
+
+impl<'a, T: Iterator<Item=u8> + Sized> LexiconBuilder<'a, T> {
+    pub fn new(interpreter: &'a mut Interpreter<T>, lexicon: LexiconId) -> Self {
+        Self {
+            interpreter,
+            lexicon,
+        }
+    }
+
+    /// Add a primitive to the lexicon
+    pub fn primitive(self, word_name: &str, immediate: bool, function: PrimitiveFn<T>) -> Self {
+        self.interpreter.define_primitive(self.lexicon, word_name, immediate, function);
+        self
+    }
+
+    /// Add a primitive backed by a closure to the lexicon
+    pub fn closure<F>(self, word_name: &str, immediate: bool, function: F) -> Self
+        where F: Fn(&mut Interpreter<T>) -> Result<(), KrkErr> + 'static
+    {
+        self.interpreter.define_closure(self.lexicon, word_name, immediate, function);
+        self
+    }
+
+    /// Add a list of primitives (name, immediate, function) to the lexicon
+    pub fn primitives(self, list: &[(&str, bool, PrimitiveFn<T>)]) -> Self {
+        list.iter().fold(self, |builder, (word_name, immediate, function)| builder.primitive(word_name, *immediate, *function))
+    }
+
+    /// Make the word with the given name private to the lexicon
+    pub fn private(self, word_name: &str) -> Self {
+        let (word_name, _) = word_name_from_str(word_name);
+        self.interpreter.words.lexicon_at(self.lexicon.0).set_private(&word_name, true);
+        self
+    }
+
+    /// Finish building and return the lexicon
+    pub fn build(self) -> LexiconId {
+        self.lexicon
+    }
+}
+
+// Primitives are responsible for updating the ref_count of the references they handle, see the note above Stack.
 
 fn two_num_op_template<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>, int_op: fn(KrkInt, KrkInt) -> KrkInt, flt_op: fn(KrkFlt, KrkFlt) -> KrkFlt) -> Result<(), KrkErr> {
-    if let (Some(b_cell), Some(a_cell)) = (context.stack.pop(), context.stack.pop()) {
+    if let Some((a_cell, b_cell)) = context.stack.pop_pair() {
         if let (Cell::Integer(a_int), Cell::Integer(b_int)) = (&a_cell, &b_cell) {
             context.stack.push(Cell::Integer(int_op(*a_int, *b_int)));
         }
@@ -748,6 +1840,8 @@ fn two_num_op_template<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T
             context.stack.push(Cell::Float(flt_op(*a_flt, *b_flt)));
         }
         else {
+            context.release_cell(a_cell);
+            context.release_cell(b_cell);
             return Err(KrkErr::WrongType);
         }
     }
@@ -778,7 +1872,7 @@ pub fn percent<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Re
 }
 
 pub fn two_num_comp_template<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>, int_op: fn(KrkInt, KrkInt) -> bool, flt_op: fn(KrkFlt, KrkFlt) -> bool) -> Result<(), KrkErr> {
-    if let (Some(b_cell), Some(a_cell)) = (context.stack.pop(), context.stack.pop()) {
+    if let Some((a_cell, b_cell)) = context.stack.pop_pair() {
         if let (Cell::Integer(a_int), Cell::Integer(b_int)) = (&a_cell, &b_cell) {
             context.stack.push(Cell::Integer(if int_op(*a_int, *b_int) { -1 } else { 0 }));
         }
@@ -786,6 +1880,8 @@ pub fn two_num_comp_template<T: Iterator<Item=u8> + Sized>(context: &mut Interpr
             context.stack.push(Cell::Integer(if flt_op(*a_flt, *b_flt) { -1 } else { 0 }));
         }
         else {
+            context.release_cell(a_cell);
+            context.release_cell(b_cell);
             return Err(KrkErr::WrongType);
         }
     }
@@ -804,11 +1900,13 @@ pub fn equal<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Resu
 }
 
 pub fn two_int_op_template<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>, int_op: fn(KrkInt, KrkInt) -> KrkInt) -> Result<(), KrkErr> {
-    if let (Some(b_cell), Some(a_cell)) = (context.stack.pop(), context.stack.pop()) {
+    if let Some((a_cell, b_cell)) = context.stack.pop_pair() {
         if let (Cell::Integer(a_int), Cell::Integer(b_int)) = (&a_cell, &b_cell) {
             context.stack.push(Cell::Integer(int_op(*a_int, *b_int)));
         }
         else {
+            context.release_cell(a_cell);
+            context.release_cell(b_cell);
             return Err(KrkErr::WrongType);
         }
     }
@@ -832,6 +1930,7 @@ pub fn not<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result
             context.stack.push(Cell::Integer(!*a_int));
         }
         else {
+            context.release_cell(a_cell);
             return Err(KrkErr::WrongType);
         }
     }
@@ -846,21 +1945,39 @@ pub fn open_curly<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) ->
     if name_len == 0 {
         return Err(KrkErr::EmptyTib);
     }
-    context.compiling = Some(Word::new(word_name, name_len, false, WordFlavor::Defined(DefinedWord::new())));
+    // The word is stored right away, so it can refer to itself, but it is not added to a lexicon until closed
+    let word_index = context.words.add_word(Word::new(word_name, name_len, false, WordFlavor::Defined(DefinedWord::new())));
+    context.compiling.push((word_index, context.exec_mode, context.stack.depth()));
     context.exec_mode = false;
     Ok(())
 }
 
 pub fn close_curly<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
-    if let Some(_) = context.compiling {
-        // Store compiling word to current lexicon
-        let lex_in_use = context.lex_in_use;
-        let word = core::mem::replace(&mut context.compiling, None).unwrap();
-        let word_name = word.name.clone();
-        let word_index = context.words.add_word(word);
-        let lex = context.words.lexicon_at(lex_in_use);
-        lex.add_word(word_name, word_index);
-        context.exec_mode = true;
+    if let Some(&(word_index, exec_mode, depth)) = context.compiling.last() {
+        let word = context.words.word_at(word_index).expect("Compiling word doesn't exist");
+        if word.name_len == 0 {
+            // Compiling a quotation, must be closed with "]"
+            return Err(KrkErr::NotCompiling);
+        }
+        if context.stack.depth() != depth {
+            return Err(KrkErr::Unbalanced);
+        }
+        // Store compiling word to definitions lexicon
+        let word_name = word.name;
+        context.compiling.pop();
+        // Recursive calls were compiled as a link to the word itself, use a weak self reference to avoid a cycle
+        if let Some(link_index) = context.words.lexicon_at(context.lex_in_def).find_word(&word_name) {
+            if let Some(Word { flavor: WordFlavor::Link(LinkWord { index: None }), .. }) = context.words.word_at(link_index) {
+                let defined = context.words.word_at(word_index).expect("Compiling word doesn't exist").as_defined();
+                let count = defined.replace_code_refs(link_index, word_index);
+                for _ in 0..count {
+                    context.words.release(link_index);
+                }
+            }
+        }
+        context.local_names.retain(|&(owner, _)| owner != word_index);
+        context.add_to_lexicon(context.lex_in_def, word_name, word_index);
+        context.exec_mode = exec_mode;
         Ok(())
     }
     else {
@@ -883,16 +2000,50 @@ pub fn close_parenth<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>)
 }
 
 pub fn flush<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
-    while let Some(dat) = context.stack.pop() {
-        match dat {
-            Cell::WordRef(_index, _) => { /*TODO: free word*/ },
-            Cell::AllocRef(index, _) => { context.allocs.release(index); },
-            _ => {},
-        }
+    while context.stack.size() > 0 {
+        context.consume()?;
     }
     Ok(())
 }
 
+pub fn dup<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some(cell) = context.stack.pop() {
+        context.stack.push(cell);
+        context.push_copy(cell);
+        Ok(())
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn drop<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    context.consume()
+}
+
+pub fn swap<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some((a_cell, b_cell)) = context.stack.pop_pair() {
+        context.stack.push(b_cell);
+        context.stack.push(a_cell);
+        Ok(())
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn over<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some((a_cell, b_cell)) = context.stack.pop_pair() {
+        context.stack.push(a_cell);
+        context.stack.push(b_cell);
+        context.push_copy(a_cell);
+        Ok(())
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
 pub fn size<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
     context.stack.push(Cell::Integer(context.stack.size() as KrkInt));
     Ok(())
@@ -918,25 +2069,26 @@ pub fn from_aux<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> R
     }
 }
 
-//TODO: hem consumit una ref de la pila sense decrementar RC
 pub fn mem_exlam<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
-    /*TODO
-    - Pop ref cell and dat cell, release() ref cell, if free, do nothing and end.
-    - If not free:
-      - Put dat cell at ref offset.
-      - If offset contained a ref, release() it.
-     */
-    if let (Some(ref_cell), Some(dat_cell)) = (context.stack.pop(), context.stack.pop()) {
-        match ref_cell {
+    if let Some((dat_cell, ref_cell)) = context.stack.pop_pair() {
+        let old_cell = match ref_cell {
             Cell::AllocRef(alloc_ref, offset) => {
                 if let Some(alloc) = context.allocs.alloc_at(alloc_ref) {
                     match &mut alloc.buffer {
-                        AllocBuffer::CellBuffer(_) => todo!("Implement CALLOC"),
-                        AllocBuffer::DataBuffer(buf) => {
+                        AllocBuffer::CellBuffer(buf) => {
                             if buf.len() > offset {
-                                if dat_cell.is_number() {
+                                // The reference moves from the stack to the buffer
+                                Ok(core::mem::replace(&mut buf[offset], dat_cell))
+                            }
+                            else {
+                                Err(KrkErr::IndexOutOfBounds)
+                            }
+                        },
+                        AllocBuffer::DataBuffer(buf) => {
+                            if buf.len() > offset {
+                                if dat_cell.is_number() {
                                     buf[offset] = dat_cell;
-                                    Ok(())
+                                    Ok(Cell::Empty)
                                 }
                                 else {
                                     Err(KrkErr::WrongType)
@@ -950,7 +2102,7 @@ pub fn mem_exlam<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) ->
                             if buf.len() > offset {
                                 if dat_cell.is_number() {
                                     buf[offset] = dat_cell.as_integer() as u8;
-                                    Ok(())
+                                    Ok(Cell::Empty)
                                 }
                                 else {
                                     Err(KrkErr::WrongType)
@@ -972,20 +2124,7 @@ pub fn mem_exlam<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) ->
                     if let WordFlavor::Defined(defined) = &mut word.flavor {
                         if defined.data_len as usize > offset {
                             let offset = DEFINITION_SIZE - 1 - offset;
-                            match &defined.definition[offset] {
-                                Cell::WordRef(_index, _) => {
-                                    // TODO: decrement RC and free if necssary
-                                    todo!("Implement WordRef dec RC and free")
-                                },
-                                Cell::AllocRef(index, _) => {
-                                    if let Some(_) = context.allocs.alloc_at(*index) {
-                                        context.allocs.release(*index);
-                                    }
-                                },
-                                _ => {}
-                            }
-                            defined.definition[offset] = dat_cell;
-                            Ok(())
+                            Ok(core::mem::replace(&mut defined.definition[offset], dat_cell))
                         }
                         else {
                             Err(KrkErr::IndexOutOfBounds)
@@ -1000,6 +2139,19 @@ pub fn mem_exlam<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) ->
                 }
             },
             _ => Err(KrkErr::WrongType),
+        };
+        match old_cell {
+            Ok(old_cell) => {
+                // Release the overwritten cell first, the target could be freed with the ref cell
+                context.release_cell(old_cell);
+                context.release_cell(ref_cell);
+                Ok(())
+            },
+            Err(err) => {
+                context.release_cell(dat_cell);
+                context.release_cell(ref_cell);
+                Err(err)
+            },
         }
     }
     else {
@@ -1007,37 +2159,17 @@ pub fn mem_exlam<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) ->
     }
 }
 
-//TODO: hem consumit una ref de la pila sense decrementar RC
 pub fn mem_at<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
-    /*TODO
-    - Pop ref cell and release(), if free, do nothing and end.
-    - If not free:
-      - Read data at ref.
-      - If data read is a ref, acquire().
-     */
     if let Some(ref_cell) = context.stack.pop() {
-        match ref_cell {
+        let cell = match ref_cell {
             Cell::AllocRef(alloc_ref, offset) => {
                 if let Some(alloc) = context.allocs.alloc_at(alloc_ref) {
-                    match &mut alloc.buffer {
-                        AllocBuffer::CellBuffer(_) => todo!("Implement CALLOC"),
-                        AllocBuffer::DataBuffer(buf) => {
-                            if buf.len() > offset {
-                                context.stack.push(buf[offset]);
-                                Ok(())
-                            }
-                            else {
-                                Err(KrkErr::IndexOutOfBounds)
-                            }
+                    match &alloc.buffer {
+                        AllocBuffer::CellBuffer(buf) | AllocBuffer::DataBuffer(buf) => {
+                            buf.get(offset).copied().ok_or(KrkErr::IndexOutOfBounds)
                         },
                         AllocBuffer::ByteBuffer(buf) => {
-                            if buf.len() > offset {
-                                context.stack.push(Cell::Integer(buf[offset] as KrkInt));
-                                Ok(())
-                            }
-                            else {
-                                Err(KrkErr::IndexOutOfBounds)
-                            }
+                            buf.get(offset).map(|b| Cell::Integer(*b as KrkInt)).ok_or(KrkErr::IndexOutOfBounds)
                         },
                         AllocBuffer::Empty => Err(KrkErr::WrongBuffer),
                     }
@@ -1050,8 +2182,7 @@ pub fn mem_at<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Res
                 if let Some(word) = context.words.word_at(word_ref) {
                     if let WordFlavor::Defined(defined) = &mut word.flavor {
                         if defined.data_len as usize > offset {
-                            let _offset = DEFINITION_SIZE - 1 - offset;
-                            todo!("Get data, increment RC and put into stack")
+                            Ok(defined.definition[DEFINITION_SIZE - 1 - offset])
                         }
                         else {
                             Err(KrkErr::IndexOutOfBounds)
@@ -1066,7 +2197,13 @@ pub fn mem_at<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Res
                 }
             },
             _ => Err(KrkErr::WrongType),
+        };
+        if let Ok(cell) = cell {
+            // The stack gets its own reference, acquired before the ref cell is released
+            context.push_copy(cell);
         }
+        context.release_cell(ref_cell);
+        cell.map(|_| ())
     }
     else {
         Err(KrkErr::StackUnderun)
@@ -1074,21 +2211,70 @@ pub fn mem_at<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Res
 }
 
 pub fn mem_offset<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
-    if let (Some(ref_cell), Some(offset_cell)) = (context.stack.pop(), context.stack.pop()) {
-        if let Cell::Integer(offset) = offset_cell {
-            match ref_cell {
-                Cell::AllocRef(alloc_ref, current_offset) => {
-                    context.stack.push(Cell::AllocRef(alloc_ref, current_offset + offset as usize));
-                    Ok(())
-                },
-                Cell::WordRef(word_ref, current_offset) => {
-                    context.stack.push(Cell::WordRef(word_ref, current_offset + offset as usize));
-                    Ok(())
-                },
-                _ => Err(KrkErr::WrongType),
+    if let Some((offset_cell, ref_cell)) = context.stack.pop_pair() {
+        // The reference moves to the new cell
+        let moved = match (ref_cell, offset_cell) {
+            (Cell::AllocRef(alloc_ref, current_offset), Cell::Integer(offset)) => {
+                current_offset.checked_add_signed(offset as isize).map(|offset| Cell::AllocRef(alloc_ref, offset)).ok_or(KrkErr::IndexOutOfBounds)
+            },
+            (Cell::WordRef(word_ref, current_offset), Cell::Integer(offset)) => {
+                current_offset.checked_add_signed(offset as isize).map(|offset| Cell::WordRef(word_ref, offset)).ok_or(KrkErr::IndexOutOfBounds)
+            },
+            _ => Err(KrkErr::WrongType),
+        };
+        match moved {
+            Ok(cell) => {
+                context.stack.push(cell);
+                Ok(())
+            },
+            Err(err) => {
+                context.release_cell(offset_cell);
+                context.release_cell(ref_cell);
+                Err(err)
+            },
+        }
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn mem_balloc<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some(size_cell) = context.stack.pop() {
+        if let Cell::Integer(size) = size_cell {
+            if size > 0 {
+                let alloc_ref = context.allocs.balloc(size as usize);
+                context.stack.push(Cell::AllocRef(alloc_ref, 0));
+                Ok(())
+            }
+            else {
+                Err(KrkErr::WrongSize)
             }
         }
         else {
+            context.release_cell(size_cell);
+            Err(KrkErr::WrongType)
+        }
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn mem_calloc<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some(size_cell) = context.stack.pop() {
+        if let Cell::Integer(size) = size_cell {
+            if size > 0 {
+                let alloc_ref = context.allocs.calloc(size as usize);
+                context.stack.push(Cell::AllocRef(alloc_ref, 0));
+                Ok(())
+            }
+            else {
+                Err(KrkErr::WrongSize)
+            }
+        }
+        else {
+            context.release_cell(size_cell);
             Err(KrkErr::WrongType)
         }
     }
@@ -1110,10 +2296,726 @@ pub fn mem_alloc<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) ->
             }
         }
         else {
+            context.release_cell(size_cell);
             Err(KrkErr::WrongType)
         }
     }
     else {
         Err(KrkErr::StackUnderun)
     }
-}
\ No newline at end of file
+}
+
+pub fn lex<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let (word_name, name_len) = context.tib.next_word();
+    if name_len == 0 {
+        return Err(KrkErr::EmptyTib);
+    }
+    context.add_lexicon(context.lex_in_def, word_name, name_len);
+    Ok(())
+}
+
+pub fn dot<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Use lexicon for word lookup
+    let lexicon = context.pop_lexicon()?;
+    context.words.acquire(lexicon);
+    context.words.release(core::mem::replace(&mut context.lex_in_use, lexicon));
+    Ok(())
+}
+
+pub fn colon<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Use lexicon for new definitions
+    let lexicon = context.pop_lexicon()?;
+    context.words.acquire(lexicon);
+    context.words.release(core::mem::replace(&mut context.lex_in_def, lexicon));
+    Ok(())
+}
+
+pub fn union<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Target lexicon is on top, the rest of the current stack are the source lexicons
+    let mut cells = Vec::with_capacity(context.stack.size());
+    while let Some(cell) = context.stack.pop() {
+        cells.push(cell);
+    }
+    let lexicons: Option<Vec<usize>> = cells.iter().map(|cell| context.lexicon_index(*cell)).collect();
+    match lexicons {
+        Some(mut lexicons) if !lexicons.is_empty() => {
+            for cell in cells {
+                context.release_cell(cell);
+            }
+            let target = lexicons.remove(0);
+            lexicons.reverse();
+            context.words.union(target, &lexicons);
+            Ok(())
+        },
+        _ => {
+            // Leave the stack untouched
+            let err = if cells.is_empty() { KrkErr::StackUnderun } else { KrkErr::WrongType };
+            for cell in cells.into_iter().rev() {
+                context.stack.push(cell);
+            }
+            Err(err)
+        },
+    }
+}
+
+pub fn import<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let source = context.pop_lexicon()?;
+    let (word_name, name_len) = context.tib.next_word();
+    if name_len == 0 {
+        return Err(KrkErr::EmptyTib);
+    }
+    context.words.import(context.lex_in_def, source, &word_name)
+}
+
+pub fn private<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Make the latest defined word private to the definitions lexicon
+    if let Some(word_index) = context.latest {
+        let word_name = context.words.word_at(word_index).expect("Latest word doesn't exist").name;
+        if context.words.lexicon_at(context.lex_in_def).set_private(&word_name, true) {
+            return Ok(());
+        }
+    }
+    Err(KrkErr::WordNotFound)
+}
+
+pub fn jmp<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let target = context.next_inline_cell()?;
+    context.jump_to(target)
+}
+
+pub fn bra<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Branch if false
+    if let Some(flag) = context.stack.pop() {
+        if let Cell::Integer(flag) = flag {
+            let target = context.next_inline_cell()?;
+            if flag == 0 {
+                context.jump_to(target)?;
+            }
+            Ok(())
+        }
+        else {
+            context.release_cell(flag);
+            Err(KrkErr::WrongType)
+        }
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn if_word<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let position = context.compile_forward_branch("bra")?;
+    context.stack.push(Cell::Integer(position as KrkInt));
+    Ok(())
+}
+
+pub fn else_word<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let position = context.compile_forward_branch("jmp")?;
+    context.resolve_forward_branch()?;
+    context.stack.push(Cell::Integer(position as KrkInt));
+    Ok(())
+}
+
+pub fn then_word<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    context.resolve_forward_branch()
+}
+
+pub fn run_do<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Loop state goes to the aux stack: exit position, limit and index
+    let exit = context.next_inline_cell()?;
+    if let Some((limit, index)) = context.stack.pop_pair() {
+        if let (Cell::Integer(_), Cell::Integer(_)) = (index, limit) {
+            context.aux.push(exit);
+            context.aux.push(limit);
+            context.aux.push(index);
+            Ok(())
+        }
+        else {
+            context.release_cell(index);
+            context.release_cell(limit);
+            Err(KrkErr::WrongType)
+        }
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn run_loop<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let target = context.next_inline_cell()?;
+    let (index, limit, exit) = context.pop_loop()?;
+    if index + 1 < limit {
+        context.aux.push(exit);
+        context.aux.push(Cell::Integer(limit));
+        context.aux.push(Cell::Integer(index + 1));
+        context.jump_to(target)?;
+    }
+    Ok(())
+}
+
+pub fn do_word<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let position = context.compile_forward_branch("(do)")?;
+    context.stack.push(Cell::Integer(position as KrkInt));
+    Ok(())
+}
+
+pub fn loop_word<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // The loop body starts right after the exit position compiled by "do"
+    let position = context.pop_control()?;
+    context.compile_backward_branch("(loop)", Cell::Integer(position + 1))?;
+    context.stack.push(Cell::Integer(position));
+    context.resolve_forward_branch()
+}
+
+pub fn loop_index<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some(index) = context.aux.peek() {
+        context.stack.push(index);
+        Ok(())
+    }
+    else {
+        Err(KrkErr::AuxStackUnderun)
+    }
+}
+
+pub fn leave<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let (_, _, exit) = context.pop_loop()?;
+    context.jump_to(exit)
+}
+
+pub fn begin<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let position = context.compiling_word()?.code_len();
+    context.stack.push(Cell::Integer(position as KrkInt));
+    Ok(())
+}
+
+pub fn until<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let position = context.pop_control()?;
+    context.compile_backward_branch("bra", Cell::Integer(position))
+}
+
+pub fn while_word<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let position = context.compile_forward_branch("bra")?;
+    context.stack.push(Cell::Integer(position as KrkInt));
+    Ok(())
+}
+
+pub fn repeat<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let while_position = context.pop_control()?;
+    let begin_position = context.pop_control()?;
+    context.compile_backward_branch("jmp", Cell::Integer(begin_position))?;
+    context.stack.push(Cell::Integer(while_position));
+    context.resolve_forward_branch()
+}
+
+pub fn self_word<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Weak reference: the ref_count is not incremented, otherwise the word would keep itself alive
+    let (word_index, _, _) = *context.compiling.last().ok_or(KrkErr::NotCompiling)?;
+    context.compile(Cell::WordRef(word_index, 0))
+}
+
+pub fn lit<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Push next cell instead of executing it
+    let cell = context.next_inline_cell()?;
+    context.push_copy(cell);
+    Ok(())
+}
+
+pub fn open_bracket<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Quotations are anonymous defined words
+    let word_index = context.words.add_word(Word::new(WordName::default(), 0, false, WordFlavor::Defined(DefinedWord::new())));
+    context.compiling.push((word_index, context.exec_mode, context.stack.depth()));
+    context.exec_mode = false;
+    Ok(())
+}
+
+pub fn close_bracket<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some(&(word_index, exec_mode, depth)) = context.compiling.last() {
+        if context.words.word_at(word_index).expect("Compiling word doesn't exist").name_len != 0 {
+            // Compiling a named word, must be closed with "}"
+            return Err(KrkErr::NotCompiling);
+        }
+        if context.stack.depth() != depth {
+            return Err(KrkErr::Unbalanced);
+        }
+        context.compiling.pop();
+        if exec_mode {
+            context.stack.push(Cell::WordRef(word_index, 0));
+            context.exec_mode = true;
+            Ok(())
+        }
+        else {
+            // Quotation inside a definition, compile it as a literal
+            context.compile_root_word("lit")?;
+            context.compile(Cell::WordRef(word_index, 0))
+        }
+    }
+    else {
+        Err(KrkErr::NotCompiling)
+    }
+}
+
+pub fn exe<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    match context.stack.pop() {
+        Some(cell @ Cell::WordRef(word_index, _)) => {
+            let result = context.exec_word(word_index);
+            context.release_cell(cell);
+            result
+        },
+        Some(cell) => {
+            context.release_cell(cell);
+            Err(KrkErr::WrongType)
+        },
+        None => Err(KrkErr::StackUnderun),
+    }
+}
+
+pub fn catch<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    match context.stack.pop() {
+        Some(cell @ Cell::WordRef(word_index, _)) => {
+            context.catch_frames.push(CatchFrame {
+                stack: context.stack.mark(),
+                aux_len: context.aux.len(),
+                ret_len: context.ret.len(),
+                local_frames_len: context.local_frames.len(),
+                cep: context.current_cep,
+            });
+            // Return through the catch end word, that pushes a 0 if no error happened
+            if let Some(cep) = context.current_cep {
+                context.ret.push(cep);
+            }
+            context.current_cep = Some(CEP::new(context.catch_end));
+            let result = context.exec_word(word_index);
+            context.release_cell(cell);
+            result
+        },
+        Some(cell) => {
+            context.release_cell(cell);
+            Err(KrkErr::WrongType)
+        },
+        None => Err(KrkErr::StackUnderun),
+    }
+}
+
+pub fn end_catch<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if context.catch_frames.pop().is_some() {
+        context.stack.push(Cell::Integer(0));
+        Ok(())
+    }
+    else {
+        Err(KrkErr::NotExecuting)
+    }
+}
+
+pub fn throw<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    match context.stack.pop() {
+        Some(Cell::Integer(0)) => Ok(()),
+        Some(Cell::Integer(code)) => Err(KrkErr::from_code(code).unwrap_or(KrkErr::Thrown(code))),
+        Some(cell) => {
+            context.release_cell(cell);
+            Err(KrkErr::WrongType)
+        },
+        None => Err(KrkErr::StackUnderun),
+    }
+}
+
+pub fn create<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    context.create_word()?;
+    Ok(())
+}
+
+pub fn variable<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let word_index = context.create_word()?;
+    context.words.word_at(word_index).expect("Created word doesn't exist").as_defined().compile_data(Cell::Integer(0));
+    Ok(())
+}
+
+pub fn constant<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    context.create_reader("@")
+}
+
+pub fn value<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Reads its data with (value) instead of @, so that `to` can tell it from a constant
+    context.create_reader("(value)")
+}
+
+pub fn to<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let (word_name, name_len) = context.tib.next_word();
+    if name_len == 0 {
+        return Err(KrkErr::EmptyTib);
+    }
+    if !context.exec_mode {
+        if let Some(local) = context.find_local(&word_name) {
+            context.compile_root_word("(local!)")?;
+            return context.compile(Cell::Integer(local as KrkInt));
+        }
+    }
+    let (word_index, _) = context.lookup(&word_name).ok_or(KrkErr::WordNotFound)?;
+    if !context.is_value(word_index) {
+        return Err(KrkErr::WrongType);
+    }
+    if context.exec_mode {
+        context.push_copy(Cell::WordRef(word_index, 0));
+        mem_exlam(context)
+    }
+    else {
+        context.compile_root_word("lit")?;
+        context.compile(Cell::WordRef(word_index, 0))?;
+        context.words.acquire(word_index);
+        context.compile_root_word("!")
+    }
+}
+
+pub fn data<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some(cell) = context.stack.pop() {
+        let result = match context.data_target() {
+            Ok(defined) => if defined.compile_data(cell) { Ok(()) } else { Err(KrkErr::DefinitionFull) },
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            context.release_cell(cell);
+        }
+        result
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn does<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // The latest word will push its data reference and then run the rest of the current word
+    let mut cep = context.current_cep.ok_or(KrkErr::NotExecuting)?;
+    let word_index = context.latest.ok_or(KrkErr::WordNotFound)?;
+    if word_index == cep.word_index {
+        // A word can't give itself a runtime behaviour, it would call itself forever
+        return Err(KrkErr::WrongType);
+    }
+    let lit_index = context.root_word("lit")?;
+    let does_index = context.root_word("(does)")?;
+    let old_code = if let Some(Word { flavor: WordFlavor::Defined(defined), .. }) = context.words.word_at(word_index) {
+        let old_code = defined.clear_code();
+        defined.compile_code(Cell::WordRef(lit_index, 0));
+        defined.compile_code(Cell::WordRef(word_index, 0)); // weak self reference
+        defined.compile_code(Cell::WordRef(does_index, 0));
+        defined.compile_code(Cell::WordRef(cep.word_index, 0));
+        defined.compile_code(Cell::Integer(cep.cell_index as KrkInt));
+        old_code
+    }
+    else {
+        return Err(KrkErr::WrongType);
+    };
+    for index in [lit_index, does_index, cep.word_index] {
+        context.words.acquire(index);
+    }
+    for cell in old_code {
+        if !matches!(cell, Cell::WordRef(index, _) if index == word_index) {
+            context.release_cell(cell);
+        }
+    }
+    // The rest of the current word is the runtime behaviour, don't execute it now
+    cep.finish(&mut context.words);
+    context.current_cep = Some(cep);
+    Ok(())
+}
+
+pub fn run_does<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let (Cell::WordRef(word_index, _), Cell::Integer(cell_index)) = (context.next_inline_cell()?, context.next_inline_cell()?) {
+        let mut cep = CEP::new(word_index);
+        cep.jump(cell_index as u8);
+        context.call(cep);
+        Ok(())
+    }
+    else {
+        Err(KrkErr::WrongType)
+    }
+}
+
+pub fn me<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Reference to the data region of the word being executed
+    let word_index = context.current_cep.ok_or(KrkErr::NotExecuting)?.word_index;
+    context.push_copy(Cell::WordRef(word_index, 0));
+    Ok(())
+}
+
+pub fn here<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let position = context.compiling_word()?.code_len();
+    context.stack.push(Cell::Integer(position as KrkInt));
+    Ok(())
+}
+
+pub fn allot<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Append empty data cells to the word being compiled, or the latest defined
+    match context.stack.pop() {
+        Some(Cell::Integer(count)) if count >= 0 => {
+            let defined = context.data_target()?;
+            for _ in 0..count {
+                if !defined.compile_data(Cell::Integer(0)) {
+                    return Err(KrkErr::DefinitionFull);
+                }
+            }
+            Ok(())
+        },
+        Some(Cell::Integer(_)) => Err(KrkErr::WrongSize),
+        Some(cell) => {
+            context.release_cell(cell);
+            Err(KrkErr::WrongType)
+        },
+        None => Err(KrkErr::StackUnderun),
+    }
+}
+
+pub fn literal<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    if let Some(cell) = context.stack.pop() {
+        context.compile_literal(cell)
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn open_lit<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Execute words while compiling, results are collected in a nested stack
+    if context.compiling.is_empty() {
+        return Err(KrkErr::NotCompiling);
+    }
+    context.stack.start_stack();
+    context.exec_mode = true;
+    Ok(())
+}
+
+pub fn close_lit<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Compile all cells of the nested stack as literals and go back to compile mode
+    if context.compiling.is_empty() || !context.exec_mode {
+        return Err(KrkErr::NotCompiling);
+    }
+    let mut cells = Vec::with_capacity(context.stack.size());
+    while let Some(cell) = context.stack.pop() {
+        cells.push(cell);
+    }
+    if context.stack.end_stack().is_none() {
+        for cell in cells {
+            context.release_cell(cell);
+        }
+        return Err(KrkErr::LevelStackUnderun);
+    }
+    context.exec_mode = false;
+    let mut cells = cells.into_iter().rev();
+    while let Some(cell) = cells.next() {
+        if let Err(err) = context.compile_literal(cell) {
+            for cell in cells {
+                context.release_cell(cell);
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+pub fn immediate<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let word_index = context.latest.ok_or(KrkErr::WordNotFound)?;
+    context.words.word_at(word_index).expect("Latest word doesn't exist").set_immediate(true);
+    Ok(())
+}
+
+pub fn bar<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Locals declaration, only allowed at the beginning of a named word: { name | a b c | ... }
+    let word_index = if let Some(&(word_index, _, _)) = context.compiling.last() {
+        word_index
+    }
+    else {
+        return Err(KrkErr::NotCompiling);
+    };
+    let word = context.words.word_at(word_index).expect("Compiling word doesn't exist");
+    if word.name_len == 0 || word.as_defined().code_len() != 0 {
+        return Err(KrkErr::NotCompiling);
+    }
+    let mut count = 0;
+    loop {
+        let (word_name, name_len) = context.tib.next_word();
+        if name_len == 0 {
+            return Err(KrkErr::EmptyTib);
+        }
+        if name_len == 1 && word_name[0] == b'|' {
+            break;
+        }
+        context.local_names.push((word_index, word_name));
+        count += 1;
+    }
+    context.compile_root_word("(locals)")?;
+    context.compile(Cell::Integer(count))
+}
+
+pub fn run_locals<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Move cells from the stack to a new frame of locals, the top of the stack is the last local
+    if let Cell::Integer(count) = context.next_inline_cell()? {
+        let count = count as usize;
+        if context.stack.size() < count {
+            return Err(KrkErr::StackUnderun);
+        }
+        let base = context.locals.len();
+        context.locals.resize(base + count, Cell::Empty);
+        for i in (base..base + count).rev() {
+            context.locals[i] = context.stack.pop().expect("Stack underflow");
+        }
+        context.local_frames.push((context.ret.len(), base));
+        Ok(())
+    }
+    else {
+        Err(KrkErr::WrongType)
+    }
+}
+
+pub fn local_at<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let index = context.local_index()?;
+    let cell = context.locals[index];
+    context.push_copy(cell);
+    Ok(())
+}
+
+pub fn local_exlam<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let index = context.local_index()?;
+    if let Some(cell) = context.stack.pop() {
+        let old_cell = core::mem::replace(&mut context.locals[index], cell);
+        context.release_cell(old_cell);
+        Ok(())
+    }
+    else {
+        Err(KrkErr::StackUnderun)
+    }
+}
+
+pub fn string_literal<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Bytes up to "]$" are stored in a byte buffer: $[ Hello, world! ]$
+    let bytes = context.tib.next_until(b"]$").ok_or(KrkErr::EmptyTib)?;
+    let alloc_ref = context.allocs.balloc_from(bytes);
+    if context.exec_mode {
+        context.stack.push(Cell::AllocRef(alloc_ref, 0));
+        Ok(())
+    }
+    else {
+        // The definition owns the buffer
+        let result = context.compile(Cell::AllocRef(alloc_ref, 0));
+        if result.is_err() {
+            context.release_cell(Cell::AllocRef(alloc_ref, 0));
+        }
+        result
+    }
+}
+
+// String lexicon: strings are byte buffers, operations consume their arguments and produce new buffers
+
+pub fn string_len<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let string = context.pop_string()?;
+    context.stack.push(Cell::Integer(string.len() as KrkInt));
+    Ok(())
+}
+
+pub fn string_concat<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let b_string = context.pop_string()?;
+    let mut a_string = context.pop_string()?;
+    a_string.extend_from_slice(&b_string);
+    context.push_string(a_string);
+    Ok(())
+}
+
+pub fn string_substr<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // ( string start count -- substring )
+    let count = context.pop_integer()?;
+    let start = context.pop_integer()?;
+    let string = context.pop_string()?;
+    let end = match start.checked_add(count) {
+        Some(end) if start >= 0 && count >= 0 && end as usize <= string.len() => end as usize,
+        _ => return Err(KrkErr::IndexOutOfBounds),
+    };
+    context.push_string(string[start as usize..end].to_vec());
+    Ok(())
+}
+
+pub fn string_compare<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // -1, 0 or 1 if a is smaller, equal or bigger than b
+    let b_string = context.pop_string()?;
+    let a_string = context.pop_string()?;
+    context.stack.push(Cell::Integer(a_string.cmp(&b_string) as KrkInt));
+    Ok(())
+}
+
+pub fn string_find<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // Position of the first occurrence of the pattern, or -1 if not found
+    let pattern = context.pop_string()?;
+    let string = context.pop_string()?;
+    let position = if pattern.is_empty() {
+        Some(0)
+    }
+    else {
+        string.windows(pattern.len()).position(|window| window == pattern.as_slice())
+    };
+    context.stack.push(Cell::Integer(position.map_or(-1, |p| p as KrkInt)));
+    Ok(())
+}
+
+pub fn string_upper<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let string = context.pop_string()?;
+    context.push_string(string.to_ascii_uppercase());
+    Ok(())
+}
+
+pub fn string_lower<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let string = context.pop_string()?;
+    context.push_string(string.to_ascii_lowercase());
+    Ok(())
+}
+
+pub fn string_split<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    // ( string separator -- part1 ... partN N )
+    let separator = context.pop_string()?;
+    let string = context.pop_string()?;
+    if separator.is_empty() {
+        return Err(KrkErr::WrongSize);
+    }
+    let mut count = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i + separator.len() <= string.len() {
+        if string[i..].starts_with(&separator) {
+            context.push_string(string[start..i].to_vec());
+            count += 1;
+            i += separator.len();
+            start = i;
+        }
+        else {
+            i += 1;
+        }
+    }
+    context.push_string(string[start..].to_vec());
+    context.stack.push(Cell::Integer(count + 1));
+    Ok(())
+}
+
+pub fn string_to_num<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let string = context.pop_string()?;
+    let string = core::str::from_utf8(&string).map_err(|_| KrkErr::WrongType)?.trim();
+    if let Ok(int) = string.parse::<KrkInt>() {
+        context.stack.push(Cell::Integer(int));
+    }
+    else if let Ok(flt) = string.parse::<KrkFlt>() {
+        context.stack.push(Cell::Float(flt));
+    }
+    else {
+        return Err(KrkErr::WrongType);
+    }
+    Ok(())
+}
+
+pub fn num_to_string<T: Iterator<Item=u8> + Sized>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    let string = match context.stack.pop() {
+        Some(Cell::Integer(int)) => format!("{}", int),
+        // Debug format always keeps the decimal point, so the string is parsed back as a float
+        Some(Cell::Float(flt)) => format!("{:?}", flt),
+        Some(cell) => {
+            context.release_cell(cell);
+            return Err(KrkErr::WrongType);
+        },
+        None => return Err(KrkErr::StackUnderun),
+    };
+    context.push_string(string.into_bytes());
+    Ok(())
+}
@@ -27,10 +27,9 @@ fn main() {
         stack_print
     ".bytes());
 
-    // Root lexicon is alwais at index 0
-    interpreter.define_primitive(0, "debug_print", false, _debug_print);
-    interpreter.define_primitive(0, "stack_print", false, _stack_print);
-    interpreter.define_primitive(0, "sum", false, _sum);
+    interpreter.extend_lexicon(interpreter.root()).primitives(&[
+        ("debug_print", false, _debug_print), ("stack_print", false, _stack_print), ("sum", false, _sum),
+    ]);
 
     while match interpreter.run_step() {
         Err(e) => { println!("Exception = {:?}", e); false },
@@ -38,9 +37,9 @@ fn main() {
     } {}
 
     println!("--------------------------------------");
-    let mut i = 0;
     println!("--- Words:");
-    while let Some(word) = interpreter.words.word_at(i) {
+    for i in 0..interpreter.words.len() {
+        let word = if let Some(word) = interpreter.words.word_at(i) { word } else { continue };
         let word_name_str = unsafe {
             let arr = core::slice::from_raw_parts(word.name.as_ptr(), word.name_len as usize);
             core::str::from_utf8_unchecked(arr)
@@ -51,10 +50,9 @@ fn main() {
             WordFlavor::Lexicon(_) => println!("({}) Word `{}` ref_count = {} lexicon",  i, word_name_str, word.ref_count),
             WordFlavor::Link(_) => println!("({}) Word `{}` ref_count = {} link",  i, word_name_str, word.ref_count),
         }
-        i += 1;
     }
     println!("--- Allocs:");
-    i = 0;
+    let mut i = 0;
     while let Some(alloc) = interpreter.allocs.alloc_at(i) {
         println!("({}) alloc = {:?}", i, alloc);
         i += 1;
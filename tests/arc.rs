@@ -0,0 +1,216 @@
+mod common;
+
+use common::*;
+use kriek::{KrkErr, Cell};
+
+/// All allocs are free, and each one was freed exactly once: a slot freed twice would be handed out twice
+fn assert_all_freed(interpreter: &mut Vm) {
+    assert_eq!(interpreter.allocs.in_use(), 0);
+    let mut slots = Vec::new();
+    while interpreter.allocs.ref_count(slots.len()).is_none() && interpreter.allocs.alloc_at(slots.len()).is_some() {
+        slots.push(interpreter.allocs.alloc(1));
+    }
+    let count = slots.len();
+    slots.sort();
+    slots.dedup();
+    assert_eq!(slots.len(), count);
+}
+
+fn top_alloc(interpreter: &mut Vm) -> usize {
+    match interpreter.stack.pop() {
+        Some(Cell::AllocRef(index, _)) => {
+            interpreter.stack.push(Cell::AllocRef(index, 0));
+            index
+        },
+        other => panic!("Expected an alloc reference, found {:?}", other),
+    }
+}
+
+#[test]
+fn new_alloc_is_owned_by_the_stack() {
+    let mut interpreter = run("3 alloc");
+    let index = top_alloc(&mut interpreter);
+    assert_eq!(interpreter.allocs.ref_count(index), Some(1));
+    interpreter = run("3 alloc drop");
+    assert_all_freed(&mut interpreter);
+}
+
+#[test]
+fn dup_and_over_acquire() {
+    let mut interpreter = run("3 alloc dup 1 over");
+    let index = top_alloc(&mut interpreter);
+    assert_eq!(interpreter.allocs.ref_count(index), Some(3));
+    assert_all_freed(&mut run("3 alloc dup 1 over flush"));
+    assert_all_freed(&mut run("3 alloc 1 swap drop drop"));
+}
+
+#[test]
+fn store_and_fetch_in_data_buffer() {
+    assert_all_freed(&mut run("3 alloc dup 7 swap 1 swap offset ! 1 swap offset @ drop"));
+}
+
+#[test]
+fn aux_stack_moves_references() {
+    let interpreter = run("2 alloc ->aux");
+    assert_eq!(interpreter.allocs.ref_count(0), Some(1));
+    assert_all_freed(&mut run("2 alloc ->aux aux-> drop"));
+}
+
+#[test]
+fn cell_buffer_holds_references() {
+    let mut interpreter = run("$[ abc ]$ 2 calloc dup ->aux ! aux->");
+    let index = top_alloc(&mut interpreter);
+    assert_eq!(interpreter.allocs.ref_count(index), Some(1));
+    assert_eq!(interpreter.allocs.in_use(), 2);
+    // Fetching gives the stack its own reference to the string
+    let mut interpreter = run("$[ abc ]$ 2 calloc dup ->aux ! aux-> dup @");
+    let index = top_alloc(&mut interpreter);
+    assert_eq!(interpreter.allocs.ref_count(index), Some(2));
+    // Unless the buffer was the last reference to it
+    let mut interpreter = run("$[ abc ]$ 2 calloc dup ->aux ! aux-> @");
+    let index = top_alloc(&mut interpreter);
+    assert_eq!(interpreter.allocs.ref_count(index), Some(1));
+    assert_eq!(interpreter.allocs.in_use(), 1);
+    assert_all_freed(&mut run("$[ abc ]$ 2 calloc dup ->aux ! aux-> dup @ drop drop"));
+}
+
+#[test]
+fn overwritten_references_are_released() {
+    assert_all_freed(&mut run("$[ abc ]$ 1 calloc dup ->aux ! $[ def ]$ aux-> dup ->aux ! aux-> drop"));
+}
+
+#[test]
+fn freeing_a_cell_buffer_is_recursive() {
+    // Buffer holding a buffer holding a string
+    let mut interpreter = run("$[ abc ]$ 1 calloc dup ->aux ! aux-> 1 calloc dup ->aux ! aux->");
+    assert_eq!(interpreter.allocs.in_use(), 3);
+    interpreter = run("$[ abc ]$ 1 calloc dup ->aux ! aux-> 1 calloc dup ->aux ! aux-> drop");
+    assert_all_freed(&mut interpreter);
+}
+
+#[test]
+fn variables_own_their_references() {
+    let interpreter = run("variable v $[ abc ]$ v ! v @ drop");
+    assert_eq!(interpreter.allocs.ref_count(0), Some(1));
+    assert_all_freed(&mut run("variable v $[ abc ]$ v ! 0 v ! v @ drop"));
+    assert_all_freed(&mut run("$[ abc ]$ value v 0 to v"));
+}
+
+#[test]
+fn definitions_own_their_literals() {
+    let mut interpreter = run("{ s $[ abc ]$ } s s");
+    let index = top_alloc(&mut interpreter);
+    assert_eq!(interpreter.allocs.ref_count(index), Some(3));
+    interpreter = run("{ s $[ abc ]$ } s s drop drop");
+    assert_eq!(interpreter.allocs.ref_count(0), Some(1));
+}
+
+#[test]
+fn locals_are_released_on_return() {
+    assert_all_freed(&mut run("{ f | a b | b a } 2 alloc 3 alloc f drop drop"));
+    assert_all_freed(&mut run("{ f | a | a a drop } 2 alloc f drop"));
+    assert_all_freed(&mut run("{ f | a | 0 to a } 2 alloc f"));
+}
+
+#[test]
+fn string_words_consume_their_arguments() {
+    assert_all_freed(&mut run("$[ abc ]$ $[ def ]$ String:concat String:len drop"));
+    assert_all_freed(&mut run("$[ a,b,c ]$ $[ , ]$ String:split flush"));
+    assert_all_freed(&mut run("$[ 42 ]$ String:>num String:num> String:upper drop"));
+}
+
+#[test]
+fn caught_errors_release_dropped_cells() {
+    let mut interpreter = run("[ 2 alloc 3 alloc 1 throw ] catch drop");
+    assert_all_freed(&mut interpreter);
+}
+
+#[test]
+fn failed_store_releases_its_arguments() {
+    let (mut interpreter, err) = run_err("2 alloc 2 alloc !");
+    assert!(matches!(err, KrkErr::WrongType));
+    assert_all_freed(&mut interpreter);
+}
+
+fn words_in_use(src: &str) -> usize {
+    run(src).words.in_use()
+}
+
+#[test]
+fn dropped_quotations_are_freed() {
+    let baseline = words_in_use("");
+    assert_eq!(words_in_use("[ 1 2 + ] drop"), baseline);
+    assert_eq!(words_in_use("[ 1 2 + ] exe drop"), baseline);
+    assert_eq!(words_in_use("[ [ 1 ] ] exe exe drop"), baseline);
+    assert_eq!(words_in_use("variable v [ 1 ] v ! 0 v !"), words_in_use("variable v"));
+}
+
+#[test]
+fn redefined_words_are_freed_and_reused() {
+    let baseline = words_in_use("{ f 1 }");
+    assert_eq!(words_in_use("{ f 1 } { f 2 } { f 3 }"), baseline);
+    assert_eq!(words_in_use("{ f | n | n 0 = if 0 else n 1 - f then } 3 f drop { f 1 }"), baseline);
+    // The new definition is created before the old one is freed, from then on slots are reused
+    assert_eq!(run("{ f 1 } { f 2 } { f 3 } { f 4 }").words.len(), run("{ f 1 } { f 2 }").words.len());
+    assert_all_freed(&mut run("{ s $[ abc ]$ } { s 1 }"));
+}
+
+#[test]
+fn referenced_words_survive_redefinition() {
+    let mut interpreter = run("{ a 1 } { b a } { a 2 } b a");
+    assert!(matches!(interpreter.stack.pop(), Some(Cell::Integer(2))));
+    assert!(matches!(interpreter.stack.pop(), Some(Cell::Integer(1))));
+    // Once the last reference is gone, the old definition is freed too
+    assert_eq!(words_in_use("{ a 1 } { b a } { a 2 } { b 3 }"), words_in_use("{ a 2 } { b 3 }"));
+}
+
+#[test]
+fn aborted_definitions_are_freed() {
+    let (mut interpreter, _) = run_err("{ w 1 2 Nope:word");
+    // Freed when back in TIB mode
+    assert_eq!(interpreter.run_step().ok(), Some(false));
+    assert_eq!(interpreter.words.in_use(), words_in_use(""));
+}
+
+#[test]
+fn lexicons_in_use_are_kept_alive() {
+    let mut interpreter = run("lex Foo Foo : { hi 1 } Root : Foo . lex Foo hi Root .");
+    assert!(matches!(interpreter.stack.pop(), Some(Cell::Integer(1))));
+    // The replaced Foo and its words are freed once no longer in use
+    assert_eq!(interpreter.words.in_use(), words_in_use("lex Foo"));
+}
+
+/// The script fails with the expected error, releasing all its allocs
+fn assert_freed_on_error(src: &str, expected: KrkErr) {
+    let (mut interpreter, err) = run_err(src);
+    assert_eq!(err.code(), expected.code(), "{}", src);
+    kriek::flush(&mut interpreter).expect("Flush failed");
+    assert_all_freed(&mut interpreter);
+}
+
+#[test]
+fn failed_primitives_release_their_arguments() {
+    assert_freed_on_error("$[ a ]$ 1 +", KrkErr::WrongType);
+    assert_freed_on_error("1 $[ a ]$ <", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ 1 and", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ not", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ 1 offset", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ $[ b ]$ offset", KrkErr::WrongType);
+    assert_freed_on_error("-1 $[ a ]$ offset", KrkErr::IndexOutOfBounds);
+    assert_freed_on_error("$[ a ]$ alloc", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ exe", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ catch", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ throw", KrkErr::WrongType);
+    assert_freed_on_error("$[ a ]$ String:num>", KrkErr::WrongType);
+}
+
+#[test]
+fn failed_pairs_keep_the_cell_they_popped() {
+    // The single cell stays on the stack, and is released by the test
+    for word in ["+", "<", "and", "swap", "over", "!", "offset"] {
+        let (interpreter, err) = run_err(&format!("$[ a ]$ {}", word));
+        assert!(matches!(err, KrkErr::StackUnderun), "{}", word);
+        assert_eq!(interpreter.stack.size(), 1, "{}", word);
+    }
+    assert_freed_on_error("$[ a ]$ !", KrkErr::StackUnderun);
+}
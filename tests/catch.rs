@@ -0,0 +1,55 @@
+mod common;
+
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn catch_pushes_zero_when_nothing_is_thrown() {
+    assert_eq!(eval("[ 1 2 + ] catch"), vec![3, 0]);
+}
+
+#[test]
+fn catch_restores_the_stack_and_pushes_the_code() {
+    assert_eq!(eval("1 [ 2 3 5 throw ] catch"), vec![1, 5]);
+    assert_eq!(eval("[ 1 nosuchword ] catch"), vec![KrkErr::UnresolvedLink.code()]);
+    assert_eq!(eval("{ f 1 2 3 throw } [ f ] catch"), vec![3]);
+}
+
+#[test]
+fn catch_restores_the_stack_depth() {
+    assert_eq!(eval("1 2 [ drop drop 7 throw ] catch"), vec![0, 0, 7]);
+    assert_eq!(eval("[ ( 1 2 3 throw ] catch size"), vec![3, 1]);
+}
+
+#[test]
+fn thrown_codes_are_kept() {
+    assert_eq!(eval("[ -99 throw ] catch"), vec![-99]);
+    assert_eq!(eval("[ 99999 throw ] catch"), vec![99999]);
+    assert!(matches!(error("-99 throw"), KrkErr::Thrown(-99)));
+    assert!(matches!(error("-1 throw"), KrkErr::StackUnderun));
+}
+
+#[test]
+fn uncaught_errors_unwind_nested_stacks() {
+    let (interpreter, err) = run_err("1 ( 2 nosuch");
+    assert!(matches!(err, KrkErr::WordNotFound));
+    assert_eq!(interpreter.stack.size(), 1);
+    let (mut interpreter, _) = run_err("{ k lit[ 1 nosuch ]lit");
+    assert_eq!(interpreter.stack.size(), 0);
+    assert!(matches!(interpreter.run_step(), Err(KrkErr::NotCompiling)));
+}
+
+#[test]
+fn catch_restores_closed_nested_stacks() {
+    assert_eq!(eval("( [ ) 5 throw ] catch ) size"), vec![5, 1]);
+}
+
+#[test]
+fn uncaught_errors_drop_loop_state() {
+    let (mut interpreter, err) = run_err("{ f 3 0 do i nosuch loop } 7 ->aux f aux->");
+    assert!(matches!(err, KrkErr::UnresolvedLink));
+    // Cells in the aux stack before running f are kept
+    assert!(matches!(interpreter.run_step(), Ok(true)));
+    assert!(matches!(interpreter.run_step(), Ok(false)));
+    assert_eq!(ints(&mut interpreter), vec![0, 7]);
+}
@@ -0,0 +1,51 @@
+#![allow(dead_code)]
+
+use kriek::{Interpreter, KrkErr, KrkInt, Cell};
+
+pub type Vm = Interpreter<std::vec::IntoIter<u8>>;
+
+pub fn vm(src: &str) -> Vm {
+    Interpreter::new(src.as_bytes().to_vec().into_iter())
+}
+
+/// Run a script to the end, panicking on errors
+pub fn run(src: &str) -> Vm {
+    let mut interpreter = vm(src);
+    while interpreter.run_step().expect("Script failed") {}
+    interpreter
+}
+
+/// Run a script until it fails
+pub fn run_err(src: &str) -> (Vm, KrkErr) {
+    let mut interpreter = vm(src);
+    loop {
+        match interpreter.run_step() {
+            Ok(true) => {},
+            Ok(false) => panic!("Script didn't fail"),
+            Err(err) => return (interpreter, err),
+        }
+    }
+}
+
+/// Error of a failing script
+pub fn error(src: &str) -> KrkErr {
+    run_err(src).1
+}
+
+/// Take all integers from the stack, bottom first
+pub fn ints(interpreter: &mut Vm) -> Vec<KrkInt> {
+    let mut ints = Vec::new();
+    while let Some(cell) = interpreter.stack.pop() {
+        match cell {
+            Cell::Integer(int) => ints.push(int),
+            other => panic!("Expected an integer, found {:?}", other),
+        }
+    }
+    ints.reverse();
+    ints
+}
+
+/// Integers left on the stack by a script
+pub fn eval(src: &str) -> Vec<KrkInt> {
+    ints(&mut run(src))
+}
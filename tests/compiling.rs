@@ -0,0 +1,38 @@
+mod common;
+
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn literal_compiles_the_top_of_the_stack() {
+    assert_eq!(eval("{ f 7 here literal } f f"), vec![7, 1, 7, 1]);
+}
+
+#[test]
+fn literal_needs_a_definition() {
+    let (interpreter, err) = run_err("$[ abc ]$ literal");
+    assert!(matches!(err, KrkErr::NotCompiling));
+    assert_eq!(interpreter.allocs.in_use(), 0);
+}
+
+#[test]
+fn immediate_words_compile_literals() {
+    assert_eq!(eval("{ answer 42 (literal) } immediate { f answer 1 } f"), vec![42, 1]);
+    assert!(matches!(error("{ imm 42 literal }"), KrkErr::StackUnderun));
+}
+
+#[test]
+fn lit_brackets_compile_their_results() {
+    assert_eq!(eval("{ f lit[ 2 3 * 4 ]lit + } f"), vec![10]);
+    assert_eq!(eval("{ f lit[ ]lit 1 } f"), vec![1]);
+    assert_eq!(eval("{ f lit[ [ 5 ] ]lit exe } f f"), vec![5, 5]);
+    assert!(matches!(error("lit["), KrkErr::NotCompiling));
+    assert!(matches!(error("]lit"), KrkErr::NotCompiling));
+}
+
+#[test]
+fn immediate_words_run_while_compiling() {
+    assert_eq!(eval("variable n { count n @ 1 + n ! } immediate { f count 1 count } n @ f"), vec![2, 1]);
+    // Cells they leave on the stack would be taken as control structures
+    assert!(matches!(error("{ two 2 } immediate { f 1 two }"), KrkErr::Unbalanced));
+}
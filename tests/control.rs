@@ -0,0 +1,91 @@
+mod common;
+
+use std::cell::Cell as StdCell;
+use std::rc::Rc;
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn if_then_runs_on_true() {
+    assert_eq!(eval("{ f if 1 then 2 } -1 f 0 f"), vec![1, 2, 2]);
+}
+
+#[test]
+fn if_else_then_picks_a_branch() {
+    assert_eq!(eval("{ f if 1 else 2 then 3 } -1 f 0 f"), vec![1, 3, 2, 3]);
+    // Nested
+    assert_eq!(eval("{ f if if 1 else 2 then else 3 then } -1 -1 f 0 -1 f 0 f"), vec![1, 2, 3]);
+}
+
+#[test]
+fn branches_need_a_definition() {
+    assert!(matches!(error("{ f then }"), KrkErr::StackUnderun));
+    assert!(matches!(error("-1 if"), KrkErr::NotCompiling));
+}
+
+#[test]
+fn control_structures_must_be_closed_in_their_definition() {
+    assert!(matches!(error("{ f 1 if 2 }"), KrkErr::Unbalanced));
+    assert!(matches!(error("{ f 3 0 do i }"), KrkErr::Unbalanced));
+    assert!(matches!(error("{ f begin 1 }"), KrkErr::Unbalanced));
+    assert!(matches!(error("{ f -1 if [ 1 then ] }"), KrkErr::StackUnderun));
+    // Cells of the user are not taken as branch positions
+    let (mut interpreter, err) = run_err("0 { f 1 then }");
+    assert!(matches!(err, KrkErr::StackUnderun));
+    assert_eq!(ints(&mut interpreter), vec![0]);
+    assert!(matches!(error("0 { f begin until until }"), KrkErr::StackUnderun));
+}
+
+#[test]
+fn do_loop_counts_from_index_to_limit() {
+    assert_eq!(eval("{ f 4 0 do i loop } f"), vec![0, 1, 2, 3]);
+    assert_eq!(eval("{ f 3 0 do 2 0 do i loop loop } f"), vec![0, 1, 0, 1, 0, 1]);
+}
+
+#[test]
+fn leave_exits_the_loop() {
+    assert_eq!(eval("{ f 10 0 do i i 2 = if leave then loop 99 } f"), vec![0, 1, 2, 99]);
+}
+
+#[test]
+fn begin_until_and_begin_while_repeat() {
+    assert_eq!(eval("{ f 0 begin 1 + dup 3 = until } f"), vec![3]);
+    assert_eq!(eval("{ f 0 begin dup 3 < while 1 + repeat } f"), vec![3]);
+}
+
+#[test]
+fn self_recurses_into_the_word_being_defined() {
+    assert_eq!(eval("{ fact dup 1 < if drop 1 else dup 1 - self * then } 5 fact"), vec![120]);
+    assert_eq!(eval("[ dup 0 = if else 1 - self then ] 3 swap exe"), vec![0]);
+}
+
+/// Deepest return stack seen by the "depth" word while running a script
+fn max_return_depth(src: &str) -> usize {
+    let mut interpreter = vm(src);
+    let root = interpreter.root();
+    let max_depth = Rc::new(StdCell::new(0));
+    let max = max_depth.clone();
+    interpreter.extend_lexicon(root).closure("depth", false, move |context| {
+        max.set(max.get().max(context.return_depth()));
+        Ok(())
+    });
+    while interpreter.run_step().expect("Script failed") {}
+    max_depth.get()
+}
+
+#[test]
+fn tail_calls_dont_grow_the_return_stack() {
+    assert!(max_return_depth("{ down dup 0 = if else 1 - depth down then } { g 1000 down drop } g") <= 2);
+    // Calls that are not the last cell do
+    assert_eq!(max_return_depth("{ down dup 0 = if else 1 - depth down 0 drop then } 50 down"), 49);
+}
+
+#[test]
+fn quotations_are_pushed_and_executed_with_exe() {
+    assert_eq!(eval("[ 1 2 + ] exe"), vec![3]);
+    assert_eq!(eval("{ twice | q | q exe q exe } 0 [ 1 + ] twice"), vec![2]);
+    // Quotations inside definitions are literals
+    assert_eq!(eval("{ f [ 5 ] } f exe f exe"), vec![5, 5]);
+    assert!(matches!(error("1 exe"), KrkErr::WrongType));
+    assert!(matches!(error("{ f ] }"), KrkErr::NotCompiling));
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn me_reaches_the_running_word_data() {
+    assert_eq!(eval("{ f me @ 1 + me ! me @ } 0 data f f f"), vec![1, 2, 3]);
+    assert!(matches!(error("me"), KrkErr::NotExecuting));
+}
+
+#[test]
+fn here_is_the_compile_position() {
+    assert_eq!(eval("{ f 1 2 here literal } f"), vec![1, 2, 2]);
+    assert!(matches!(error("here"), KrkErr::NotCompiling));
+}
+
+#[test]
+fn allot_appends_data_cells() {
+    assert_eq!(eval("create x 2 allot x 1 swap offset @"), vec![0]);
+    assert_eq!(eval("{ arr create allot does offset } 3 arr a 7 1 a ! 1 a @ 0 a @"), vec![7, 0]);
+    assert!(matches!(error("create x -1 allot"), KrkErr::WrongSize));
+}
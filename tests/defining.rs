@@ -0,0 +1,54 @@
+mod common;
+
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn does_gives_children_shared_behaviour() {
+    assert_eq!(eval("{ konst create data does @ } 5 konst five 7 konst seven five seven five"), vec![5, 7, 5]);
+}
+
+#[test]
+fn does_code_only_runs_in_children() {
+    assert_eq!(eval("{ k create 0 data 1 does drop 2 } k x"), vec![1]);
+    assert_eq!(eval("{ k create 0 data 1 does drop 2 } k x x"), vec![1, 2]);
+}
+
+#[test]
+fn children_keep_their_own_data() {
+    let counters = "{ counter create 0 data does dup @ 1 + swap over swap ! } counter a counter b";
+    assert_eq!(eval(&format!("{} a a a b", counters)), vec![1, 2, 3, 1]);
+    assert_eq!(eval("{ pair create data data does dup @ swap 1 swap offset @ } 1 2 pair p p"), vec![2, 1]);
+}
+
+#[test]
+fn does_needs_a_running_word() {
+    assert!(matches!(error("create x does"), KrkErr::NotExecuting));
+    // The latest word must not be the running one
+    assert!(matches!(error("{ k does 1 } k"), KrkErr::WrongType));
+}
+
+#[test]
+fn variables_are_read_and_written_through_their_data() {
+    assert_eq!(eval("variable v v @ 5 v ! v @"), vec![0, 5]);
+    assert_eq!(eval("variable v { set v ! } { get v @ } 3 set get"), vec![3]);
+}
+
+#[test]
+fn constants_push_their_data() {
+    assert_eq!(eval("5 constant c c c"), vec![5, 5]);
+    assert!(matches!(error("constant c"), KrkErr::StackUnderun));
+}
+
+#[test]
+fn values_are_changed_with_to() {
+    assert_eq!(eval("5 value v v 7 to v v"), vec![5, 7]);
+    assert_eq!(eval("5 value v { set to v } { get v } 9 set get"), vec![9]);
+}
+
+#[test]
+fn to_only_changes_values() {
+    assert!(matches!(error("5 constant c 7 to c"), KrkErr::WrongType));
+    assert!(matches!(error("variable v 7 to v"), KrkErr::WrongType));
+    assert!(matches!(error("{ g 1 } { f 7 to g }"), KrkErr::WrongType));
+}
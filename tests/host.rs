@@ -0,0 +1,66 @@
+mod common;
+
+use std::cell::Cell as StdCell;
+use std::rc::Rc;
+use common::*;
+use kriek::{Interpreter, KrkErr, Cell};
+
+fn forty_two<T: Iterator<Item=u8>>(context: &mut Interpreter<T>) -> Result<(), KrkErr> {
+    context.stack.push(Cell::Integer(42));
+    Ok(())
+}
+
+fn run_vm(interpreter: &mut Vm) {
+    while interpreter.run_step().expect("Script failed") {}
+}
+
+#[test]
+fn builder_registers_primitives_in_a_new_lexicon() {
+    let mut interpreter = vm("Host:answer Host . answer");
+    let host = interpreter.new_lexicon("Host")
+        .primitives(&[("answer", false, forty_two), ("secret", false, forty_two)])
+        .private("secret")
+        .build();
+    assert!(interpreter.find_in_lexicon(host, "answer").is_some());
+    assert!(interpreter.find_in_lexicon(host, "nope").is_none());
+    run_vm(&mut interpreter);
+    assert_eq!(ints(&mut interpreter), vec![42, 42]);
+
+    let mut interpreter = vm("Host:secret");
+    interpreter.new_lexicon("Host").primitive("secret", false, forty_two).private("secret").build();
+    assert!(matches!(interpreter.run_step(), Err(KrkErr::WordNotFound)));
+}
+
+#[test]
+fn builder_extends_existing_lexicons() {
+    let mut interpreter = vm("answer");
+    let root = interpreter.root();
+    interpreter.extend_lexicon(root).primitive("answer", false, forty_two);
+    run_vm(&mut interpreter);
+    assert_eq!(ints(&mut interpreter), vec![42]);
+}
+
+#[test]
+fn closures_keep_host_state() {
+    let counter = Rc::new(StdCell::new(0));
+    let mut interpreter = vm("tick tick { t3 tick } t3");
+    let root = interpreter.root();
+    let state = counter.clone();
+    interpreter.extend_lexicon(root).closure("tick", false, move |context| {
+        state.set(state.get() + 1);
+        context.stack.push(Cell::Integer(state.get()));
+        Ok(())
+    });
+    run_vm(&mut interpreter);
+    assert_eq!(ints(&mut interpreter), vec![1, 2, 3]);
+    assert_eq!(counter.get(), 3);
+}
+
+#[test]
+fn closure_errors_are_interpreter_errors() {
+    let mut interpreter = vm("[ fail ] catch");
+    let root = interpreter.root();
+    interpreter.extend_lexicon(root).closure("fail", false, |_| Err(KrkErr::WrongSize));
+    run_vm(&mut interpreter);
+    assert_eq!(ints(&mut interpreter), vec![KrkErr::WrongSize.code()]);
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use common::*;
+use kriek::{Cell, KrkErr};
+
+#[test]
+fn lex_creates_a_lexicon_for_definitions() {
+    assert_eq!(eval("lex Foo Foo : { x 5 } Foo . x"), vec![5]);
+    assert!(matches!(error("lex Foo Foo : { x 5 } Root : x"), KrkErr::WordNotFound));
+}
+
+#[test]
+fn selecting_a_lexicon_changes_lookup() {
+    assert_eq!(eval("lex A lex B A : { x 1 } B : { x 2 } A . x B . x"), vec![1, 2]);
+}
+
+#[test]
+fn root_words_are_visible_from_any_lexicon() {
+    assert_eq!(eval("lex Foo Foo . 1 2 +"), vec![3]);
+    // Words of the lexicon in use come first
+    assert_eq!(eval("lex Foo Foo : { + 42 } Foo . 1 2 +"), vec![1, 2, 42]);
+}
+
+#[test]
+fn union_merges_lexicons_into_the_target() {
+    assert_eq!(eval("lex A lex B lex C A : { x 1 } B : { y 2 } { x 3 } A B C union C . x y"), vec![3, 2]);
+}
+
+#[test]
+fn union_with_a_non_lexicon_leaves_the_stack_untouched() {
+    let (mut interpreter, err) = run_err("lex A lex B 1 A B union");
+    assert!(matches!(err, KrkErr::WrongType));
+    assert_eq!(interpreter.stack.size(), 3);
+    interpreter.stack.pop();
+    interpreter.stack.pop();
+    assert_eq!(ints(&mut interpreter), vec![1]);
+    assert!(matches!(error("union"), KrkErr::StackUnderun));
+}
+
+#[test]
+fn import_copies_a_single_word() {
+    assert_eq!(eval("lex A lex B A : { x 1 } { y 2 } B : A import x B . x"), vec![1]);
+    assert!(matches!(error("lex A lex B A : { x 1 } { y 2 } B : A import x B . y"), KrkErr::WordNotFound));
+    assert!(matches!(error("lex A A import nope"), KrkErr::WordNotFound));
+}
+
+#[test]
+fn qualified_names_reach_other_lexicons() {
+    assert_eq!(eval("lex A A : { x 7 } Root : A:x"), vec![7]);
+    assert_eq!(eval("lex A A : { x 7 } Root : { y A:x 1 + } y"), vec![8]);
+    assert!(matches!(error("lex A A:nope"), KrkErr::WordNotFound));
+    assert!(matches!(error("{ y Nope:x }"), KrkErr::WordNotFound));
+}
+
+#[test]
+fn forward_references_resolve_when_defined() {
+    assert_eq!(eval("{ a b 1 + } { b 3 } a"), vec![4]);
+    // Mutual recursion
+    assert_eq!(eval("{ ev | n | n 0 = if 1 else n 1 - od then } { od | n | n 0 = if 0 else n 1 - ev then } 4 ev 3 ev"), vec![1, 0]);
+    assert!(matches!(error("{ a b } a"), KrkErr::UnresolvedLink));
+}
+
+#[test]
+fn private_words_are_hidden_outside_their_lexicon() {
+    let defs = "lex A A : { helper 2 } private { x helper 3 * } ";
+    assert_eq!(eval(&format!("{}A . x helper", defs)), vec![6, 2]);
+    assert_eq!(eval(&format!("{}Root : A:x", defs)), vec![6]);
+    assert!(matches!(error(&format!("{}Root : A:helper", defs)), KrkErr::WordNotFound));
+    assert!(matches!(error(&format!("{}Root : A import helper", defs)), KrkErr::WordNotFound));
+    let (mut interpreter, err) = run_err(&format!("{}Root : lex B A B union B . x helper", defs));
+    assert!(matches!(err, KrkErr::WordNotFound));
+    assert_eq!(ints(&mut interpreter), vec![6]);
+}
+
+#[test]
+fn removed_words_are_released() {
+    let mut interpreter = vm("{ x 1 } 2");
+    while interpreter.run_step().unwrap() {}
+    let in_use = interpreter.words.in_use();
+    let root = interpreter.root();
+    assert!(interpreter.remove_word(root, "x"));
+    assert!(!interpreter.remove_word(root, "x"));
+    assert!(interpreter.find_in_lexicon(root, "x").is_none());
+    // Freed once the interpreter is back in TIB mode
+    assert_eq!(interpreter.run_step().ok(), Some(false));
+    assert_eq!(interpreter.words.in_use(), in_use - 1);
+}
+
+#[test]
+fn lookup_tells_which_lexicon_a_word_came_from() {
+    let (x, _) = kriek::word_name_from_str("x");
+    let (dup, _) = kriek::word_name_from_str("dup");
+    let (qualified, _) = kriek::word_name_from_str("Foo:x");
+    let mut interpreter = run("lex Foo Foo : { x 5 } Foo .");
+    let root = interpreter.root();
+    let foo = interpreter.find_in_lexicon(root, "Foo").expect("Foo not defined").index();
+    let x_index = interpreter.lookup(&x).expect("x not found").0;
+    assert_eq!(interpreter.lookup(&x), Some((x_index, foo)));
+    assert_eq!(interpreter.lookup(&dup).map(|(_, lexicon)| lexicon), Some(root.index()));
+    let mut interpreter = run("lex Foo Foo : { x 5 } Root .");
+    assert_eq!(interpreter.lookup(&x), None);
+    assert_eq!(interpreter.lookup(&qualified), Some((x_index, foo)));
+}
+
+/// The script leaves a lexicon that still exists on the stack
+fn assert_lexicon_on_top(src: &str) {
+    let mut interpreter = run(src);
+    match interpreter.stack.pop() {
+        Some(Cell::WordRef(index, _)) => assert!(interpreter.words.lexicon(index).is_some(), "{}", src),
+        other => panic!("Expected a lexicon, found {:?}", other),
+    }
+}
+
+#[test]
+fn replacing_the_entry_of_a_lexicon_for_itself_keeps_it_alive() {
+    assert_lexicon_on_top("lex A A : { A 1 } Root : 1 drop A");
+    assert_lexicon_on_top("lex A lex X X : { A 1 } Root : ( X A ) union 1 drop A");
+    assert_eq!(eval("{ Root 1 } lex A A . A : 1 drop 2"), vec![2]);
+}
+
+#[test]
+fn removing_the_entry_of_a_lexicon_for_itself_keeps_it_alive() {
+    let mut interpreter = vm("1 drop Foo . 2");
+    let foo = interpreter.new_lexicon("Foo").build();
+    assert!(interpreter.remove_word(foo, "Foo"));
+    while interpreter.run_step().expect("Script failed") {}
+    assert_eq!(ints(&mut interpreter), vec![2]);
+    assert!(interpreter.words.lexicon(foo.index()).is_some());
+}
+
+#[test]
+fn lexicons_keep_their_words_in_definition_order() {
+    let name = |name: &str| kriek::word_name_from_str(name).0;
+    let mut interpreter = run("lex Foo Foo : { b 1 } { a 2 } { b 3 }");
+    let root = interpreter.root();
+    let foo = interpreter.find_in_lexicon(root, "Foo").expect("Foo not defined").index();
+    let lexicon = interpreter.words.lexicon(foo).expect("Foo is not a lexicon");
+    assert!(lexicon.contains(&name("a")));
+    assert!(!lexicon.contains(&name("c")));
+    // Redefined words move to the end
+    let names: Vec<_> = lexicon.iter().map(|(word_name, _)| *word_name).collect();
+    assert_eq!(names, vec![name("Foo"), name("a"), name("b")]);
+}
+
+#[test]
+fn all_lexicons_can_be_listed() {
+    let mut interpreter = run("lex Foo { x 1 }");
+    let root = interpreter.root();
+    let foo = interpreter.find_in_lexicon(root, "Foo").expect("Foo not defined").index();
+    let x = interpreter.find_in_lexicon(root, "x").expect("x not defined").index();
+    let lexicons: Vec<_> = interpreter.words.lexicons().collect();
+    assert!(lexicons.contains(&root.index()));
+    assert!(lexicons.contains(&foo));
+    assert!(!lexicons.contains(&x));
+}
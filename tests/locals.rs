@@ -0,0 +1,39 @@
+mod common;
+
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn locals_take_their_values_from_the_stack() {
+    assert_eq!(eval("{ f | a b | b a } 1 2 f"), vec![2, 1]);
+    assert_eq!(eval("{ sq | n | n n * } 7 sq"), vec![49]);
+}
+
+#[test]
+fn to_changes_a_local() {
+    assert_eq!(eval("{ f | a | a 1 + to a a a } 1 f"), vec![2, 2]);
+}
+
+#[test]
+fn each_call_has_its_own_locals() {
+    assert_eq!(eval("{ fact | n | n 1 < if 1 else n 1 - fact n * then } 5 fact"), vec![120]);
+}
+
+#[test]
+fn locals_must_be_declared_first() {
+    assert!(matches!(error("{ f 1 | a | a }"), KrkErr::NotCompiling));
+    assert!(matches!(error("| a |"), KrkErr::NotCompiling));
+    assert!(matches!(error("{ f | a | } f"), KrkErr::StackUnderun));
+}
+
+#[test]
+fn quotations_cant_use_outer_locals() {
+    assert!(matches!(error("{ f | a | [ a ] exe }"), KrkErr::WordNotFound));
+    assert_eq!(eval("{ f | a | a [ 1 + ] exe } 1 f"), vec![2]);
+}
+
+#[test]
+fn words_cant_use_the_locals_of_their_caller() {
+    let src = "{ k | a | create a data does a 1 + } 5 k x { user | z | x z } 9 user";
+    assert!(matches!(error(src), KrkErr::IndexOutOfBounds));
+}
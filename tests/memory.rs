@@ -0,0 +1,28 @@
+mod common;
+
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn cell_buffers_store_numbers_and_references() {
+    assert_eq!(eval("2 calloc dup ->aux 5 swap ! aux-> @"), vec![5]);
+    assert_eq!(eval("2 calloc dup ->aux $[ abc ]$ swap 1 swap offset ! aux-> 1 swap offset @ String:len"), vec![3]);
+    assert_eq!(eval("1 calloc dup ->aux [ 1 2 + ] swap ! aux-> @ exe"), vec![3]);
+}
+
+#[test]
+fn new_cell_buffers_are_zeroed() {
+    assert_eq!(eval("3 calloc 2 swap offset @"), vec![0]);
+}
+
+#[test]
+fn data_buffers_only_store_numbers() {
+    assert_eq!(eval("2 alloc dup ->aux 5 swap ! aux-> @"), vec![5]);
+    assert!(matches!(error("$[ abc ]$ 2 alloc !"), KrkErr::WrongType));
+}
+
+#[test]
+fn out_of_bounds_access_fails() {
+    assert!(matches!(error("2 calloc 2 swap offset @"), KrkErr::IndexOutOfBounds));
+    assert!(matches!(error("7 2 calloc 2 swap offset !"), KrkErr::IndexOutOfBounds));
+}
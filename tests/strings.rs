@@ -0,0 +1,80 @@
+mod common;
+
+use common::*;
+use kriek::KrkErr;
+
+#[test]
+fn string_literals_keep_their_bytes() {
+    assert_eq!(eval("$[ abc ]$ String:len"), vec![3]);
+    assert_eq!(eval("$[ a,]$ String:len"), vec![2]);
+    assert_eq!(eval("$[ Hello, world! ]$ String:len"), vec![13]);
+    assert_eq!(eval("$[  a  ]$ String:len"), vec![3]);
+    assert_eq!(eval("$[ ]$ String:len"), vec![0]);
+    assert!(matches!(error("$[ abc"), KrkErr::EmptyTib));
+}
+
+#[test]
+fn string_literals_are_compiled() {
+    assert_eq!(eval("{ s $[ abc ]$ } s String:len s $[ abc ]$ String:compare"), vec![3, 0]);
+}
+
+#[test]
+fn len_concat_and_substr() {
+    assert_eq!(eval("$[ abc ]$ $[ de ]$ String:concat String:len"), vec![5]);
+    assert_eq!(eval("$[ abcdef ]$ 1 3 String:substr $[ bcd ]$ String:compare"), vec![0]);
+    assert_eq!(eval("$[ abc ]$ 3 0 String:substr String:len"), vec![0]);
+    assert!(matches!(error("$[ abc ]$ 2 2 String:substr"), KrkErr::IndexOutOfBounds));
+    assert!(matches!(error("$[ abc ]$ -1 1 String:substr"), KrkErr::IndexOutOfBounds));
+    assert!(matches!(error("$[ abc ]$ 9223372036854775807 1 String:substr"), KrkErr::IndexOutOfBounds));
+}
+
+#[test]
+fn compare_orders_strings() {
+    assert_eq!(eval("$[ abc ]$ $[ abd ]$ String:compare"), vec![-1]);
+    assert_eq!(eval("$[ abc ]$ $[ abc ]$ String:compare"), vec![0]);
+    assert_eq!(eval("$[ b ]$ $[ abc ]$ String:compare"), vec![1]);
+    assert_eq!(eval("$[ ab ]$ $[ abc ]$ String:compare"), vec![-1]);
+}
+
+#[test]
+fn find_gives_the_first_position() {
+    assert_eq!(eval("$[ abcabc ]$ $[ ca ]$ String:find"), vec![2]);
+    assert_eq!(eval("$[ abc ]$ $[ x ]$ String:find"), vec![-1]);
+    assert_eq!(eval("$[ abc ]$ $[ ]$ String:find"), vec![0]);
+}
+
+#[test]
+fn upper_and_lower() {
+    assert_eq!(eval("$[ aBc1 ]$ String:upper $[ ABC1 ]$ String:compare"), vec![0]);
+    assert_eq!(eval("$[ aBc1 ]$ String:lower $[ abc1 ]$ String:compare"), vec![0]);
+}
+
+#[test]
+fn split_pushes_the_parts_and_their_count() {
+    let parts = "$[ a,bc,,d ]$ $[ , ]$ String:split";
+    assert_eq!(eval(&format!("{} ->aux flush aux->", parts)), vec![4]);
+    assert_eq!(eval(&format!("{} drop String:len ->aux String:len ->aux String:len ->aux String:len aux-> aux-> aux->", parts)), vec![1, 2, 0, 1]);
+    assert_eq!(eval("$[ abc ]$ $[ , ]$ String:split swap String:len"), vec![1, 3]);
+    assert!(matches!(error("$[ abc ]$ $[ ]$ String:split"), KrkErr::WrongSize));
+}
+
+#[test]
+fn numbers_to_and_from_strings() {
+    assert_eq!(eval("$[ 42 ]$ String:>num"), vec![42]);
+    assert_eq!(eval("$[ -7 ]$ String:>num"), vec![-7]);
+    assert_eq!(eval("-15 String:num> $[ -15 ]$ String:compare"), vec![0]);
+    assert_eq!(eval("$[ 1.5 ]$ String:>num String:num> $[ 1.5 ]$ String:compare"), vec![0]);
+    assert!(matches!(error("$[ abc ]$ String:>num"), KrkErr::WrongType));
+}
+
+#[test]
+fn wrong_arguments_are_released() {
+    let (interpreter, err) = run_err("$[ a ]$ 1 calloc String:len");
+    assert!(matches!(err, KrkErr::WrongBuffer));
+    assert_eq!(interpreter.allocs.in_use(), 1);
+    let (interpreter, err) = run_err("$[ a ]$ $[ b ]$ 1 String:substr");
+    assert!(matches!(err, KrkErr::WrongType));
+    assert_eq!(interpreter.allocs.in_use(), 1);
+    let (interpreter, _) = run_err("$[ a ]$ String:num>");
+    assert_eq!(interpreter.allocs.in_use(), 0);
+}